@@ -0,0 +1,352 @@
+use crate::geocoder::Geocoder;
+use crate::GeoDataAddress;
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Identifies a cached response. Includes the provider and its query parameters as well as the address, so switching
+/// providers (or their settings) never serves a stale result from another backend.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub provider: String,
+    #[serde(default)]
+    pub params: Vec<(String, String)>,
+    pub address: String,
+}
+
+impl CacheKey {
+    pub fn new(geocoder: &dyn Geocoder, address: &str) -> CacheKey {
+        CacheKey {
+            provider: geocoder.name().to_string(),
+            params: geocoder.cache_params(),
+            address: address.to_string(),
+        }
+    }
+}
+
+/// Statuses that settle an address for good. Anything else, like `REQUEST_DENIED` or `OVER_DAILY_LIMIT`, says more
+/// about the key or the quota at the time than about the address, so it's asked for again next run.
+const FINAL_STATUSES: [&str; 2] = ["OK", "ZERO_RESULTS"];
+
+/// Whether `response` is worth keeping in the cache.
+pub fn is_final(response: &GeoDataAddress) -> bool {
+    FINAL_STATUSES.contains(&response.status.as_str())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    key: CacheKey,
+    response: GeoDataAddress,
+}
+
+/// Responses from earlier runs, so a rerun doesn't pay for the same address twice.
+///
+/// The file is a log with one JSON entry per line. Each new response is appended as it arrives, so a crashed run
+/// loses at most the line it was writing, and the log is compacted back to one line per key when it's next opened.
+/// A cache without a path lives only in memory and is never written out.
+#[derive(Debug, Default)]
+pub struct Cache {
+    path: Option<PathBuf>,
+    entries: HashMap<CacheKey, GeoDataAddress>,
+    log: Option<File>,
+}
+
+/// What was read from a cache file.
+struct Loaded {
+    entries: HashMap<CacheKey, GeoDataAddress>,
+    unreadable: usize,
+    /// Whether the file holds anything besides one line per entry, i.e. superseded or unreadable lines, a torn last
+    /// line or the older single-array format.
+    untidy: bool,
+}
+
+impl Cache {
+    /// Loads the cache at `path`, starting empty if the file doesn't exist yet.
+    ///
+    /// A cache that can't be fully read, say one cut short by a crash or edited by hand, doesn't stop the run. The
+    /// entries on lines that still parse are kept, and before the file is compacted without the rest, it's copied to
+    /// `<path>.corrupt`.
+    pub fn open(path: Option<&Path>) -> Result<Cache, Error> {
+        let mut cache = Cache {
+            path: path.map(Path::to_path_buf),
+            ..Cache::default()
+        };
+
+        if let Some(path) = path.filter(|p| p.exists()) {
+            let loaded = read(path)?;
+            cache.entries = loaded.entries;
+            log!(
+                "Loaded {} cached responses from {}",
+                cache.entries.len(),
+                path.display()
+            );
+            if loaded.unreadable > 0 {
+                let mut backup = path.as_os_str().to_owned();
+                backup.push(".corrupt");
+                fs::copy(path, &backup)?;
+                log!(
+                    "Warning: skipped {} cache entries that couldn't be read. The old file was copied to {}",
+                    loaded.unreadable,
+                    Path::new(&backup).display()
+                );
+            }
+            if loaded.untidy {
+                cache.compact()?;
+            }
+        }
+
+        Ok(cache)
+    }
+
+    /// Loads the cache at `path` to only read from, like when verifying or comparing caches.
+    ///
+    /// Since nothing is geocoded into it, anything short of a fully readable cache is an error rather than a warning,
+    /// and the file is never compacted or copied.
+    pub fn open_read_only(path: &Path) -> Result<Cache, Error> {
+        if !path.exists() {
+            bail!("Cache file {} doesn't exist", path.display());
+        }
+
+        let loaded = read(path)?;
+        if loaded.unreadable > 0 {
+            bail!(
+                "Couldn't read {} of the entries in the cache at {}, it may have been cut short or edited by hand",
+                loaded.unreadable,
+                path.display()
+            );
+        }
+
+        Ok(Cache {
+            entries: loaded.entries,
+            ..Cache::default()
+        })
+    }
+
+    /// Every cached key, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &CacheKey> {
+        self.entries.keys()
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<&GeoDataAddress> {
+        self.entries.get(key)
+    }
+
+    /// Adds a response, appending it to the cache file if there is one.
+    pub fn insert(&mut self, key: CacheKey, response: GeoDataAddress) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let mut line = serde_json::to_string(&CacheEntry {
+                key: key.clone(),
+                response: response.clone(),
+            })?;
+            line.push('\n');
+
+            if self.log.is_none() {
+                self.log = Some(OpenOptions::new().create(true).append(true).open(path)?);
+            }
+            // A single write per entry, so runs sharing a cache append whole lines rather than interleaving them.
+            self.log
+                .as_mut()
+                .expect("log was just opened")
+                .write_all(line.as_bytes())?;
+        }
+        self.entries.insert(key, response);
+
+        Ok(())
+    }
+
+    /// Rewrites the cache file with one line per entry.
+    ///
+    /// The entries go to a temporary file that then replaces the cache, so a crash partway through leaves the
+    /// previous cache intact rather than a truncated one.
+    fn compact(&mut self) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut contents = String::new();
+        for (key, response) in &self.entries {
+            contents.push_str(&serde_json::to_string(&CacheEntry {
+                key: key.clone(),
+                response: response.clone(),
+            })?);
+            contents.push('\n');
+        }
+        // Named per process, so two runs sharing a cache never write into the same temporary file.
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(format!(".{}.tmp", std::process::id()));
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)?;
+        self.log = None;
+
+        Ok(())
+    }
+}
+
+/// Reads the entries in the cache file at `path`, counting the lines that couldn't be read.
+///
+/// Caches written before the log format are a single JSON array, which is still read, entry by entry.
+fn read(path: &Path) -> Result<Loaded, Error> {
+    let contents = fs::read_to_string(path)?;
+    let trimmed = contents.trim_start();
+
+    let values: Vec<Option<CacheEntry>> = if trimmed.starts_with('[') {
+        match serde_json::from_str::<Vec<serde_json::Value>>(trimmed) {
+            Ok(values) => values.into_iter().map(|v| serde_json::from_value(v).ok()).collect(),
+            Err(_) => vec![None],
+        }
+    } else {
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).ok())
+            .collect()
+    };
+
+    let lines = values.len();
+    let unreadable = values.iter().filter(|v| v.is_none()).count();
+    let entries: HashMap<CacheKey, GeoDataAddress> =
+        values.into_iter().flatten().map(|e| (e.key, e.response)).collect();
+    let untidy =
+        trimmed.starts_with('[') || entries.len() < lines || !(contents.is_empty() || contents.ends_with('\n'));
+
+    Ok(Loaded {
+        entries,
+        unreadable,
+        untidy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geocoder::{Google, Nominatim, ProviderSettings};
+    use crate::testing::{ok, result};
+
+    fn response(township: &str) -> GeoDataAddress {
+        ok(vec![result(&[(township, &["locality", "political"])])])
+    }
+
+    fn township(response: &GeoDataAddress) -> &str {
+        &response.results[0].address_components[0].long_name
+    }
+
+    #[test]
+    fn switching_providers_misses_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        let google = Google::new(String::from("key"), ProviderSettings::default());
+        let nominatim = Nominatim::new(None, ProviderSettings::default());
+
+        let mut cache = Cache::open(Some(&path)).unwrap();
+        cache
+            .insert(CacheKey::new(&google, "1 Main St"), response("Bethel"))
+            .unwrap();
+        drop(cache);
+
+        let cache = Cache::open(Some(&path)).unwrap();
+        assert_eq!(
+            township(cache.get(&CacheKey::new(&google, "1 Main St")).unwrap()),
+            "Bethel"
+        );
+        assert!(cache.get(&CacheKey::new(&nominatim, "1 Main St")).is_none());
+    }
+
+    #[test]
+    fn provider_settings_are_part_of_the_key() {
+        let english = ProviderSettings {
+            language: Some(String::from("en")),
+            ..ProviderSettings::default()
+        };
+        let plain = Google::new(String::from("key"), ProviderSettings::default());
+
+        assert_ne!(
+            CacheKey::new(&Google::new(String::from("key"), english), "1 Main St"),
+            CacheKey::new(&plain, "1 Main St")
+        );
+    }
+
+    #[test]
+    fn reopening_compacts_superseded_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        let google = Google::new(String::from("key"), ProviderSettings::default());
+        let key = CacheKey::new(&google, "1 Main St");
+
+        let mut cache = Cache::open(Some(&path)).unwrap();
+        cache.insert(key.clone(), response("Old")).unwrap();
+        cache.insert(key.clone(), response("New")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        drop(cache);
+
+        let cache = Cache::open(Some(&path)).unwrap();
+        assert_eq!(township(cache.get(&key).unwrap()), "New");
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn concurrent_inserts_leave_a_readable_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        let google = Google::new(String::from("key"), ProviderSettings::default());
+        let cache = std::sync::Mutex::new(Cache::open(Some(&path)).unwrap());
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let (cache, google) = (&cache, &google);
+                scope.spawn(move || {
+                    for i in 0..50 {
+                        let key = CacheKey::new(google, &format!("{} Thread {} St", i, thread));
+                        cache.lock().unwrap().insert(key, response("Bethel")).unwrap();
+                    }
+                });
+            }
+        });
+        drop(cache);
+
+        let cache = Cache::open_read_only(&path).unwrap();
+        assert_eq!(cache.keys().count(), 400);
+        assert_eq!(
+            township(cache.get(&CacheKey::new(&google, "49 Thread 7 St")).unwrap()),
+            "Bethel"
+        );
+    }
+
+    #[test]
+    fn a_torn_line_is_skipped_and_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        let google = Google::new(String::from("key"), ProviderSettings::default());
+        let key = CacheKey::new(&google, "1 Main St");
+
+        let mut cache = Cache::open(Some(&path)).unwrap();
+        cache.insert(key.clone(), response("Bethel")).unwrap();
+        drop(cache);
+        let mut contents = fs::read_to_string(&path).unwrap();
+        contents.push_str("{\"provider\":\"google\",\"addr");
+        fs::write(&path, &contents).unwrap();
+
+        let error = Cache::open_read_only(&path).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Couldn't read 1 of the entries in the cache"),
+            "{}",
+            error
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+        let cache = Cache::open(Some(&path)).unwrap();
+        assert_eq!(township(cache.get(&key).unwrap()), "Bethel");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("cache.jsonl.corrupt")).unwrap(),
+            contents
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(Cache::open_read_only(&path).unwrap().keys().count(), 1);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::cli::Opts;
+use crate::credentials::Credentials;
+use crate::{geocoder, preview, retry};
+use anyhow::{bail, Error};
+use std::fmt;
+use std::time::Instant;
+
+/// The address each provider is asked for. Any well known address will do, it only has to get an answer.
+const PROBE_ADDRESS: &str = "1600 Pennsylvania Avenue NW, Washington, DC 20500";
+
+/// How one check went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// Couldn't be tried because an earlier check failed.
+    Skipped,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Pass => write!(f, "PASS"),
+            Verdict::Fail => write!(f, "FAIL"),
+            Verdict::Skipped => write!(f, "SKIPPED"),
+        }
+    }
+}
+
+/// The result of one check against one provider.
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub provider: String,
+    pub check: &'static str,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+/// Checks the credentials, endpoint and quota of every configured provider and prints a table of the results.
+/// Errors when any check failed, so it can gate a scripted run.
+pub async fn run(opts: &Opts) -> Result<(), Error> {
+    let client = opts.client()?;
+    let credentials = opts.credentials();
+
+    let mut outcomes = vec![];
+    for name in &opts.providers {
+        log!("Checking {}...", name);
+        outcomes.extend(check_provider(opts, &client, &credentials, name).await);
+    }
+
+    let rows: Vec<Vec<String>> = outcomes
+        .iter()
+        .map(|o| {
+            vec![
+                o.provider.clone(),
+                o.check.to_string(),
+                o.verdict.to_string(),
+                o.detail.clone(),
+            ]
+        })
+        .collect();
+    preview::print_table(&["Provider", "Check", "Result", "Detail"], &rows);
+
+    let failed = outcomes.iter().filter(|o| o.verdict == Verdict::Fail).count();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, outcomes.len());
+    }
+    log!("All {} checks passed, ready to geocode.", outcomes.len());
+
+    Ok(())
+}
+
+/// Runs the credentials, reachability and quota checks for the provider called `name`, with a single request.
+async fn check_provider(opts: &Opts, client: &reqwest::Client, credentials: &Credentials, name: &str) -> Vec<Outcome> {
+    let outcome = |check: &'static str, verdict: Verdict, detail: String| Outcome {
+        provider: name.to_string(),
+        check,
+        verdict,
+        detail,
+    };
+
+    // Only a key can be turned down, so only a key is reported as tried or accepted.
+    let mut has_key = false;
+    let present = match name.to_lowercase().as_str() {
+        "google" => match credentials.google_api_key() {
+            Ok(_) => {
+                has_key = true;
+                String::from("An API key is set")
+            }
+            Err(e) => {
+                let reason = String::from("No API key to try");
+                return vec![
+                    outcome("Credentials", Verdict::Fail, e.to_string()),
+                    outcome("Reachable", Verdict::Skipped, reason.clone()),
+                    outcome("Quota", Verdict::Skipped, reason),
+                ];
+            }
+        },
+        "nominatim" => match &credentials.nominatim_email {
+            Some(email) => format!("Identifying as {}", email),
+            None => String::from("None needed, though NOMINATIM_EMAIL is worth setting for big runs"),
+        },
+        _ => String::from("None needed"),
+    };
+
+    let geocoder = match geocoder::build(name, opts.provider_settings(name), credentials) {
+        Ok(geocoder) => geocoder,
+        Err(e) => {
+            return vec![
+                outcome("Credentials", Verdict::Pass, present),
+                outcome("Reachable", Verdict::Fail, e.to_string()),
+                outcome(
+                    "Quota",
+                    Verdict::Skipped,
+                    String::from("The provider couldn't be set up"),
+                ),
+            ];
+        }
+    };
+
+    let started = Instant::now();
+    let response = match geocoder.geocode(client, PROBE_ADDRESS).await {
+        Ok(response) => response,
+        Err(e) => {
+            let detail = if has_key {
+                format!("{}, but it couldn't be tried", present)
+            } else {
+                present
+            };
+            return vec![
+                outcome("Credentials", Verdict::Pass, detail),
+                outcome("Reachable", Verdict::Fail, e.to_string()),
+                outcome(
+                    "Quota",
+                    Verdict::Skipped,
+                    String::from("The endpoint couldn't be reached"),
+                ),
+            ];
+        }
+    };
+    let message = response
+        .extra
+        .get("error_message")
+        .and_then(|m| m.as_str())
+        .unwrap_or(&response.status)
+        .to_string();
+
+    let reachable = outcome(
+        "Reachable",
+        Verdict::Pass,
+        format!("Answered {} in {}ms", response.status, started.elapsed().as_millis()),
+    );
+    if response.status == "REQUEST_DENIED" {
+        return vec![
+            outcome("Credentials", Verdict::Fail, message),
+            reachable,
+            outcome("Quota", Verdict::Skipped, String::from("The request was denied")),
+        ];
+    }
+
+    // Neither provider says how much quota is left, only when it's gone.
+    let quota = if retry::is_quota_exhausted(&response) || response.status == "OVER_QUERY_LIMIT" {
+        outcome("Quota", Verdict::Fail, message)
+    } else {
+        outcome(
+            "Quota",
+            Verdict::Pass,
+            format!("Not used up, {} doesn't report how much is left", geocoder.name()),
+        )
+    };
+
+    let detail = if has_key {
+        format!("{}, and accepted", present)
+    } else {
+        present
+    };
+    vec![outcome("Credentials", Verdict::Pass, detail), reachable, quota]
+}
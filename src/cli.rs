@@ -0,0 +1,718 @@
+use crate::config::Config;
+use crate::credentials::{self, Credentials};
+use crate::dedup::Normalization;
+use crate::geocoder::{self, Geocoder, ProviderSettings};
+use crate::input::AddressColumn;
+use crate::output::{FieldRename, Format};
+use crate::retry::RetryBudget;
+use crate::sink::Destination;
+use crate::township::{CountryTypes, MatchMode, Precedence, DEFAULT_TOWNSHIP_TYPES};
+use anyhow::{anyhow, bail, Error};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// Command line options for a geocoding run.
+#[derive(Debug, StructOpt, Serialize)]
+#[structopt(name = "township-rs", about = "Finds the township for every address in a list.")]
+pub struct Opts {
+    /// Output formats: `csv`, `parquet`, `geojson`, or `json-full` for every provider response untouched. Several
+    /// can be given at once, like `csv,geojson`, to write them all from a single geocoding pass.
+    #[structopt(long = "format", use_delimiter = true, default_value = "csv")]
+    pub formats: Vec<Format>,
+
+    /// Renames a property of the GeoJSON output, like `formatted_address=full_address`, to match an existing schema.
+    /// May be repeated. The `json-full` output is the provider's response as is, so it keeps the provider's names.
+    #[structopt(long = "rename-field", number_of_values = 1)]
+    pub renames: Vec<FieldRename>,
+
+    /// The files of addresses to geocode, instead of being asked for one. Several files are combined into a single
+    /// run unless `--parallel-files` is set.
+    #[structopt(long = "input", parse(from_os_str), number_of_values = 1)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Geocodes each `--input` file concurrently as a separate run, sharing the cache, HTTP client and retry budget.
+    /// Each file's output, failures report and so on get the file's name as a prefix, so `--input a.txt` writes
+    /// `a.output.csv` and `a.failures.csv`. The outputs must be local paths, not stdout or a URL.
+    #[structopt(long, requires = "inputs", conflicts_with = "preview")]
+    pub parallel_files: bool,
+
+    /// Where to write the results. Repeat it once per format to name each file, or give it once to share its name
+    /// between formats with a per-format extension. Defaults to `output.<ext>`. Besides a local path this can be `-`
+    /// for stdout, which sends the log to stderr instead, an `http(s)://` URL to PUT the output to, or an
+    /// `s3://bucket/key` URL.
+    #[structopt(long = "output", parse(from_os_str), number_of_values = 1)]
+    pub outputs: Vec<PathBuf>,
+
+    /// Where to write the addresses that couldn't be resolved.
+    #[structopt(long, parse(from_os_str), default_value = "failures.csv")]
+    pub failures: PathBuf,
+
+    /// Also writes just the input addresses that failed, one per line and without reasons, so the file can be fed
+    /// straight back in as input.
+    #[structopt(long, parse(from_os_str))]
+    pub failures_plain: Option<PathBuf>,
+
+    /// How many times to retry a single address after a transient failure.
+    #[structopt(long, default_value = "3")]
+    pub max_retries: u32,
+
+    /// How many addresses to send the first provider per request. Only `json-post` takes batches, other providers
+    /// still get one request per address. Anything a batch doesn't settle is retried one address at a time.
+    #[structopt(long, default_value = "1")]
+    pub batch_size: usize,
+
+    /// Response statuses to retry, like `OVER_QUERY_LIMIT,PROXY_ERROR`, replacing each provider's own. Google's are
+    /// `OVER_QUERY_LIMIT` and `UNKNOWN_ERROR`, and Nominatim has none.
+    #[structopt(long, use_delimiter = true)]
+    pub retry_statuses: Vec<String>,
+
+    /// HTTP status codes to retry, replacing the default `408,429,500,502,503,504`. Dropped connections and other
+    /// failures without a status are always retried.
+    #[structopt(long, use_delimiter = true)]
+    pub retry_http_codes: Vec<u16>,
+
+    /// Caps the number of retries across the whole run. Once spent, failures are reported without retrying.
+    #[structopt(long)]
+    pub max_total_retries: Option<usize>,
+
+    /// When a provider's daily quota runs out, waits this many minutes and carries on instead of failing the rest
+    /// of the run. Google's quota resets at midnight Pacific time.
+    #[structopt(long)]
+    pub quota_wait: Option<f64>,
+
+    /// The longest to wait for the quota across the whole run, in minutes, before giving up on it.
+    #[structopt(long, default_value = "1440")]
+    pub max_quota_wait: f64,
+
+    /// How many seconds a whole request, from connecting to reading the last of the response, may take before it's
+    /// abandoned and retried.
+    #[structopt(long)]
+    pub timeout: Option<f64>,
+
+    /// How many seconds connecting to a provider, DNS lookup and handshakes included, may take. Set it lower than
+    /// `--timeout` to fail fast on unreachable hosts while still giving slow responses time.
+    #[structopt(long)]
+    pub timeout_connect: Option<f64>,
+
+    /// Address component types that count as a township, from most to least specific. Defaults to
+    /// `locality,administrative_area_level_3,administrative_area_level_2`.
+    #[structopt(long, use_delimiter = true)]
+    pub township_types: Vec<String>,
+
+    /// How the order of `--township-types` is applied. `strict` picks the component with the most specific type
+    /// wherever it is in the result, and `first-match` picks the first component with any of the types in the
+    /// provider's order, as earlier versions did.
+    #[structopt(long, default_value = "strict")]
+    pub match_mode: MatchMode,
+
+    /// Prints how each township was picked: the types searched, what every component of the result matched, which
+    /// one won and why, and any normalization or provider fallback along the way.
+    #[structopt(long)]
+    pub explain: bool,
+
+    /// Like `--explain`, but prints each trace as a line of JSON.
+    #[structopt(long)]
+    pub explain_json: bool,
+
+    /// Township types for the results from one country, like `DE=administrative_area_level_3,locality`, where the
+    /// country is the result's `country` component. Give it once per country. Countries without their own use
+    /// `--township-types`.
+    #[structopt(long, number_of_values = 1)]
+    pub country_township_types: Vec<CountryTypes>,
+
+    /// Skips the first line of a plain text input when it looks like a header, like `Address` or `Street Address`,
+    /// rather than geocoding it as an address.
+    #[structopt(long)]
+    pub auto_skip_header: bool,
+
+    /// The header of the column holding the addresses when the input is a CSV file. Defaults to `Address`.
+    #[structopt(long)]
+    pub address_column: Option<String>,
+
+    /// The zero-based position of the address column, for CSV files without a header row.
+    #[structopt(long, conflicts_with = "address-column")]
+    pub address_column_index: Option<usize>,
+
+    /// How addresses are matched when collapsing duplicates: `exact`, `trim`, `case-insensitive` or `alnum`.
+    #[structopt(long, default_value = "case-insensitive")]
+    pub dedup_normalization: Normalization,
+
+    /// Writes a JSON file listing every group of input addresses that were collapsed into one, with the normalized
+    /// address they matched on, to check that `--dedup-normalization` isn't merging distinct addresses.
+    #[structopt(long, parse(from_os_str))]
+    pub dedup_report: Option<PathBuf>,
+
+    /// The geocoding providers to use, in order: `google`, `nominatim`, `json-post` for an endpoint of your own that
+    /// takes a JSON body, or a chain like `google,nominatim` that falls back to the next provider whenever one errors
+    /// or finds nothing.
+    #[structopt(long = "provider", use_delimiter = true, default_value = "google")]
+    pub providers: Vec<String>,
+
+    /// Biases results towards a region, given as a ccTLD code like `us`. Only supported by Google.
+    #[structopt(long)]
+    pub region: Option<String>,
+
+    /// The language to return results in, like `en`.
+    #[structopt(long)]
+    pub language: Option<String>,
+
+    /// A file to cache responses in, so reruns don't pay for the same address twice. Each response is appended as it
+    /// arrives, so this is also how to resume a run that crashed: rerun it on the same input with the same cache, and
+    /// every row of the output, duplicates included, is rebuilt from the input in its original order.
+    #[structopt(long, parse(from_os_str))]
+    pub cache: Option<PathBuf>,
+
+    /// Overrides a provider's endpoint, e.g. to go through a proxy. Given as `name=url` to pick the provider, or as
+    /// just a URL for the first one in the chain. May be repeated.
+    #[structopt(long = "base-url", number_of_values = 1)]
+    pub base_urls: Vec<String>,
+
+    /// The largest response body to accept, in bytes. Addresses with bigger responses are reported as failures.
+    #[structopt(long, default_value = "5242880")]
+    pub max_response_bytes: usize,
+
+    /// Geocodes a random sample of this many addresses and prints the results instead of writing any files.
+    #[structopt(long)]
+    pub preview: Option<usize>,
+
+    /// Seeds the `--preview` sample and the `--shuffle-input` order so they're the same every run.
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Looks the addresses up in a random order instead of the file's, so runs don't work through one region at a
+    /// time. The output is still written in input order. Use `--seed` to repeat an order.
+    #[structopt(long)]
+    pub shuffle_input: bool,
+
+    /// Reports results the provider flagged as a partial match as failures instead of writing them out.
+    #[structopt(long)]
+    pub drop_partial: bool,
+
+    /// Reports results that are only a Plus Code, which Google returns when it can't find a street address, as
+    /// failures instead of writing them out.
+    #[structopt(long)]
+    pub strip_plus_codes: bool,
+
+    /// Collapses runs of whitespace (tabs, doubled and non-breaking spaces) in each address before geocoding.
+    #[structopt(long)]
+    pub normalize_whitespace: bool,
+
+    /// Text added to the start of every address before geocoding, e.g. a building or campus name.
+    #[structopt(long)]
+    pub prepend_prefix: Option<String>,
+
+    /// Text added to the end of every address before geocoding, e.g. `", Springfield, OH"` when the whole file is
+    /// known to be in one place but the addresses leave it out.
+    #[structopt(long)]
+    pub append_suffix: Option<String>,
+
+    /// Adds an "Input Address" column with each address exactly as it appeared in the input, for joining the
+    /// output back onto it. Always on with `--flatten-results`.
+    #[structopt(long)]
+    pub input_address: bool,
+
+    /// Leaves out the "Address" column, which holds the formatted address of the result the township came from.
+    /// With `--input-address`, the output reports only the address as it was given.
+    #[structopt(long)]
+    pub no_formatted_address: bool,
+
+    /// Adds a "Result Types" column with the result's own types, like `street_address` or `locality;political`.
+    #[structopt(long)]
+    pub result_types: bool,
+
+    /// Strips this country token, like `", USA"`, from the end of every formatted address. Only an exact match at
+    /// the very end is removed.
+    #[structopt(long)]
+    pub trim_formatted_address: Option<String>,
+
+    /// Adds a "Confidence" column with how sure the provider is of each result, for providers that say. Nominatim
+    /// reports its `importance` score, Google leaves the column blank.
+    #[structopt(long)]
+    pub confidence: bool,
+
+    /// Adds "Lat" and "Lng" columns with each result's coordinates.
+    #[structopt(long)]
+    pub coordinates: bool,
+
+    /// Adds a "WKT" column with each result's location as a Well-Known Text `POINT(lng lat)`, ready to load into
+    /// PostGIS. Blank when the result has no geometry.
+    #[structopt(long)]
+    pub wkt: bool,
+
+    /// Keeps the coordinates of results that only matched a region, like a town or a postal code, which are that
+    /// region's centroid, and flags them as `approximate` in a "Coord Precision" column, with `precise` for the rest.
+    /// Turns on `--coordinates`.
+    #[structopt(long)]
+    pub region_centroid: bool,
+
+    /// How many decimal places to write coordinates with in CSV output. They always use a period as the decimal
+    /// separator, whatever the system locale, so they can't be mistaken for a field delimiter.
+    #[structopt(long, default_value = "6")]
+    pub coord_precision: usize,
+
+    /// Asks for confirmation before geocoding more than this many unique addresses.
+    #[structopt(long, default_value = "1000")]
+    pub confirm_above: usize,
+
+    /// Skips the confirmation for big runs.
+    #[structopt(long, short)]
+    pub yes: bool,
+
+    /// Writes a row for every result the provider returned instead of only the best one. An ambiguous address with
+    /// three results becomes three rows, each repeating the input address in an "Input Address" column alongside
+    /// its own township and coordinates.
+    #[structopt(long)]
+    pub flatten_results: bool,
+
+    /// Logs and skips a record that can't be written instead of aborting the run. Skipped records are counted in
+    /// the summary.
+    #[structopt(long)]
+    pub continue_on_write_error: bool,
+
+    /// Re-fetches the responses in `--cache` and reports the addresses whose township has changed since they were
+    /// cached, without reading any input or writing output.
+    #[structopt(long, requires = "cache")]
+    pub verify: bool,
+
+    /// Only re-fetches a random sample of this many cached responses when verifying. Use `--seed` to repeat a sample.
+    #[structopt(long, requires = "verify")]
+    pub verify_sample: Option<usize>,
+
+    /// Compares the townships in two cache files, given as `--diff-cache old.json new.json`, and reports the
+    /// addresses whose township differs, without reading any input or making any requests.
+    #[structopt(
+        long,
+        parse(from_os_str),
+        number_of_values = 2,
+        value_names = &["old", "new"],
+        conflicts_with = "verify"
+    )]
+    pub diff_cache: Vec<PathBuf>,
+
+    /// Checks the input for blank rows, rows that can't be addresses, stray characters, duplicates and encoding
+    /// problems, prints a report and exits without geocoding anything.
+    #[structopt(long, conflicts_with_all = &["address", "enrich", "verify", "diff-cache"])]
+    pub validate_only: bool,
+
+    /// Looks up this one address and prints a table of everything about its result, from the township and the
+    /// component type it came from to the coordinates and match quality, instead of reading a file.
+    #[structopt(long, conflicts_with_all = &["inputs", "enrich", "verify", "diff-cache"])]
+    pub address: Option<String>,
+
+    /// Fills in the empty Township cells of an existing CSV, geocoding only those rows and updating the file in
+    /// place. Addresses come from `--address-column`, and every other row and column is left exactly as it was.
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["inputs", "preview", "verify", "diff-cache"])]
+    pub enrich: Option<PathBuf>,
+
+    /// Adds a "Hierarchy" column with the country, state, county and locality of each result in one path, like
+    /// `United States > Ohio > Clark County > Springfield`. Levels a result doesn't have are left out.
+    #[structopt(long)]
+    pub hierarchy_column: bool,
+
+    /// What goes between the levels of the "Hierarchy" column.
+    #[structopt(long, default_value = " > ")]
+    pub hierarchy_separator: String,
+
+    /// A file of canonical township names, one per line. Each township is fuzzy-matched to the closest one and
+    /// written to a "Canonical Township" column.
+    #[structopt(long, parse(from_os_str))]
+    pub gazetteer: Option<PathBuf>,
+
+    /// How similar, from 0 to 1, a township has to be to a gazetteer name to match it.
+    #[structopt(long, default_value = "0.85")]
+    pub gazetteer_threshold: f64,
+
+    /// The state every address should be in, as its short code like `OH`. Results anywhere else are flagged, since
+    /// they usually mean the provider picked the wrong place with the same name.
+    #[structopt(long)]
+    pub expect_state: Option<String>,
+
+    /// Sends results outside `--expect-state` to the failures report instead of writing them out.
+    #[structopt(long, requires = "expect-state")]
+    pub strict_region: bool,
+
+    /// Reports how often each address component type appeared across every result, to help pick
+    /// `--township-types` for a dataset. Printed as a table, or written as a CSV when given a path.
+    #[structopt(long)]
+    pub type_stats: Option<Option<PathBuf>>,
+
+    /// Reads the Google API key from the OS keyring, so it never has to sit in `.env` or on the command line. An
+    /// environment or config file key is used when the keyring has none.
+    #[structopt(long)]
+    pub keyring: bool,
+
+    /// The keyring service the API key is stored under.
+    #[structopt(long, default_value = "township-rs")]
+    pub keyring_service: String,
+
+    /// The keyring account the API key is stored under.
+    #[structopt(long, default_value = "google-api-key")]
+    pub keyring_account: String,
+
+    /// Compresses the CSV, GeoJSON and JSON outputs with gzip, adding `.gz` to their names. Any of them already
+    /// named `.gz` is compressed without it. Parquet is left alone, since its columns are always Snappy-compressed,
+    /// and can't be named `.gz` at all.
+    #[structopt(long)]
+    pub gzip: bool,
+
+    /// Writes a JSON manifest of the run here once it's done: the version, providers, input files and their
+    /// hashes, the options used, timings and counts. Credentials are left out.
+    #[structopt(long, parse(from_os_str))]
+    pub manifest: Option<PathBuf>,
+
+    /// A TOML file of settings, keyed by option name like `max-retries = 5`. Options on the command line win over
+    /// it. Values can use `${NAME}` to read an environment variable, e.g. `api_key = "${GOOGLE_API_KEY}"`.
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Something to do instead of a geocoding run.
+    #[structopt(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+
+    /// Credentials from the config file, keyed by the environment variable they stand in for.
+    #[structopt(skip)]
+    #[serde(skip)]
+    pub config_credentials: HashMap<String, String>,
+}
+
+/// Subcommands, given after any other options, like `township-rs --provider google,nominatim check`.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Checks each provider before a run: that its credentials are set and accepted, that its endpoint can be
+    /// reached through the configured `--base-url` and timeouts, and that its quota isn't used up. Makes one request
+    /// per provider and exits with an error if any check fails.
+    Check,
+    /// Asks for a Google API key and stores it in the OS keyring for `--keyring` to use, under `--keyring-service`
+    /// and `--keyring-account`.
+    StoreKey,
+}
+
+/// The path a per-file run writes to instead of `path`, by putting `input`'s file stem in front of its file name.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let path = for_input(Path::new("results/output.csv"), Path::new("lists/ohio.txt"));
+/// assert_eq!(path, PathBuf::from("results/ohio.output.csv"));
+/// ```
+pub fn for_input(path: &Path, input: &Path) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}", stem, name))
+}
+
+impl Opts {
+    /// Parses the command line, merging in the `--config` file if one is given.
+    pub fn load() -> Result<Opts, Error> {
+        let args: Vec<_> = env::args_os().collect();
+        let opts = Opts::from_iter(&args);
+        let path = match &opts.config {
+            Some(path) => path,
+            None => return Ok(opts),
+        };
+
+        let config = Config::load(path)?;
+        let mut opts = Opts::from_iter(config.merge_args(args));
+        opts.config_credentials = config.credentials;
+
+        Ok(opts)
+    }
+
+    /// The credentials from the config file, falling back to the environment for anything it doesn't set. With
+    /// `--keyring`, a Google API key in the keyring wins over both.
+    pub fn credentials(&self) -> Credentials {
+        let mut credentials = Credentials::from_lookup(|name| {
+            self.config_credentials
+                .get(name)
+                .cloned()
+                .or_else(|| env::var(name).ok())
+        });
+
+        if self.keyring {
+            match credentials::from_keyring(&self.keyring_service, &self.keyring_account) {
+                Ok(Some(key)) => credentials.google_api_key = Some(key),
+                Ok(None) => log!(
+                    "No API key in the keyring for {}/{}, falling back to the environment",
+                    self.keyring_service,
+                    self.keyring_account
+                ),
+                Err(e) => log!("Couldn't read the keyring ({}), falling back to the environment", e),
+            }
+        }
+
+        credentials
+    }
+
+    /// The path each format is written to.
+    ///
+    /// With one `--output` per format they're paired up in order. Otherwise the first `--output` (or `output`) is
+    /// used as a base name, taking each format's own extension when there's more than one. `--gzip` adds `.gz`.
+    ///
+    /// Any other number of `--output`s is an error, as is two formats ending up at the same path, a `.gz` name for
+    /// Parquet, or anything but a local path with `--parallel-files`.
+    pub fn output_paths(&self) -> Result<Vec<(Format, PathBuf)>, Error> {
+        if self.outputs.len() > 1 && self.outputs.len() != self.formats.len() {
+            bail!(
+                "Got {} --output paths for {} formats, expected one per format or a single base name",
+                self.outputs.len(),
+                self.formats.len()
+            );
+        }
+
+        let paths: Vec<(Format, PathBuf)> = if self.outputs.len() == self.formats.len() {
+            self.formats.iter().copied().zip(self.outputs.iter().cloned()).collect()
+        } else {
+            let base = self.outputs.first().cloned();
+            self.formats
+                .iter()
+                .map(|format| {
+                    let path = match &base {
+                        Some(base) if self.formats.len() == 1 => base.clone(),
+                        Some(base) => base.with_extension(format.extension()),
+                        None => PathBuf::from(format!("output.{}", format.extension())),
+                    };
+                    (*format, path)
+                })
+                .collect()
+        };
+
+        let paths: Vec<(Format, PathBuf)> = if !self.gzip {
+            paths
+        } else {
+            paths
+                .into_iter()
+                .map(|(format, path)| match format {
+                    _ if !format.is_text() => (format, path),
+                    _ if path == Path::new("-") || path.extension().is_some_and(|ext| ext == "gz") => (format, path),
+                    _ => {
+                        let mut compressed = path.into_os_string();
+                        compressed.push(".gz");
+                        (format, compressed.into())
+                    }
+                })
+                .collect()
+        };
+
+        for (i, (format, path)) in paths.iter().enumerate() {
+            if !format.is_text() && path.extension().is_some_and(|ext| ext == "gz") {
+                bail!(
+                    "{:?} output can't be gzipped, so {} shouldn't end in .gz",
+                    format,
+                    path.display()
+                );
+            }
+            // Each file's name goes in front of the output's, which only makes sense for a file.
+            if self.parallel_files && !matches!(Destination::from_path(path)?, Destination::File(_)) {
+                bail!(
+                    "--parallel-files writes one output per input file, so --output must be a local path, not {}",
+                    path.display()
+                );
+            }
+            if paths[..i].iter().any(|(_, earlier)| earlier == path) {
+                bail!(
+                    "More than one format would be written to {}, give each format its own --output",
+                    path.display()
+                );
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Which CSV column to read addresses from.
+    pub fn address_column(&self) -> AddressColumn {
+        match (&self.address_column_index, &self.address_column) {
+            (Some(index), _) => AddressColumn::Index(*index),
+            (None, Some(name)) => AddressColumn::Name(name.clone()),
+            (None, None) => AddressColumn::Name(String::from("Address")),
+        }
+    }
+
+    /// Builds the HTTP client every provider request goes through, with the configured timeouts.
+    pub fn client(&self) -> Result<reqwest::Client, Error> {
+        Ok(self.client_builder()?.build()?)
+    }
+
+    fn client_builder(&self) -> Result<reqwest::ClientBuilder, Error> {
+        let seconds = |value: f64, option: &str| {
+            Duration::try_from_secs_f64(value)
+                .map_err(|_| anyhow!("--{} must be a non-negative number of seconds", option))
+        };
+
+        let mut builder = reqwest::Client::builder().user_agent(crate::USER_AGENT);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(seconds(timeout, "timeout")?);
+        }
+        if let Some(timeout) = self.timeout_connect {
+            builder = builder.connect_timeout(seconds(timeout, "timeout-connect")?);
+        }
+
+        Ok(builder)
+    }
+
+    /// Builds the run's retry budget, along with how long it may wait for a used up quota.
+    pub fn retry_budget(&self) -> Result<RetryBudget, Error> {
+        let budget = RetryBudget::new(self.max_total_retries);
+        let minutes = |value: f64, option: &str| {
+            Duration::try_from_secs_f64(value * 60.0)
+                .map_err(|_| anyhow!("--{} must be a non-negative number of minutes", option))
+        };
+
+        match self.quota_wait {
+            Some(wait) => Ok(budget.with_quota_wait(
+                minutes(wait, "quota-wait")?,
+                minutes(self.max_quota_wait, "max-quota-wait")?,
+            )),
+            None => Ok(budget),
+        }
+    }
+
+    /// Builds the configured chain of providers.
+    pub fn geocoders(&self, credentials: &Credentials) -> Result<Vec<Box<dyn Geocoder>>, Error> {
+        self.providers
+            .iter()
+            .map(|name| geocoder::build(name, self.provider_settings(name), credentials))
+            .collect()
+    }
+
+    /// The settings handed to the geocoding provider called `name`.
+    pub fn provider_settings(&self, name: &str) -> ProviderSettings {
+        let is_first = self.providers.first().is_some_and(|first| first == name);
+        let base_url = self.base_urls.iter().rev().find_map(|url| match url.split_once('=') {
+            Some((provider, url)) if !provider.contains('/') => Some(url).filter(|_| provider == name),
+            _ => Some(url.as_str()).filter(|_| is_first),
+        });
+
+        ProviderSettings {
+            base_url: base_url.map(str::to_string),
+            region: self.region.clone(),
+            language: self.language.clone(),
+            max_response_bytes: self.max_response_bytes,
+            retryable_statuses: Some(self.retry_statuses.clone()).filter(|s| !s.is_empty()),
+            retryable_http_codes: Some(self.retry_http_codes.clone()).filter(|c| !c.is_empty()),
+        }
+    }
+
+    /// The configured township types, or `DEFAULT_TOWNSHIP_TYPES` when none were given, with any per-country ones.
+    pub fn precedence(&self) -> Precedence<'_> {
+        let mut precedence = Precedence::new(if self.township_types.is_empty() {
+            DEFAULT_TOWNSHIP_TYPES.to_vec()
+        } else {
+            self.township_types.iter().map(String::as_str).collect()
+        });
+        for country in &self.country_township_types {
+            let types = country.types.iter().map(String::as_str).collect();
+            precedence.countries.insert(country.country.clone(), types);
+        }
+        precedence.mode = self.match_mode;
+
+        precedence
+    }
+
+    /// Every township type given with `--township-types` or `--country-township-types`, without the defaults.
+    pub fn configured_township_types(&self) -> Vec<&str> {
+        let mut types: Vec<&str> = vec![];
+        let given = self
+            .township_types
+            .iter()
+            .chain(self.country_township_types.iter().flat_map(|c| &c.types));
+        for kind in given {
+            if !types.contains(&kind.as_str()) {
+                types.push(kind);
+            }
+        }
+
+        types
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_timeouts_are_applied_to_the_client() {
+        let opts = Opts::from_iter(&["township-rs", "--timeout", "10", "--timeout-connect", "2.5"]);
+        let builder = format!("{:?}", opts.client_builder().unwrap());
+        assert!(builder.contains("connect_timeout: 2.5s"), "{}", builder);
+        assert!(builder.contains(" timeout: 10s"), "{}", builder);
+
+        let builder = format!("{:?}", Opts::from_iter(&["township-rs"]).client_builder().unwrap());
+        assert!(!builder.contains("timeout"), "{}", builder);
+    }
+
+    #[test]
+    fn parallel_files_need_local_outputs() {
+        for output in ["-", "https://example.com/upload", "s3://bucket/output.csv"] {
+            let opts = Opts::from_iter(&[
+                "township-rs",
+                "--input",
+                "a.txt",
+                "--input",
+                "b.txt",
+                "--parallel-files",
+                "--output",
+                output,
+            ]);
+            assert_eq!(
+                opts.output_paths().unwrap_err().to_string(),
+                format!(
+                    "--parallel-files writes one output per input file, so --output must be a local path, not {}",
+                    output
+                )
+            );
+        }
+
+        let opts = Opts::from_iter(&[
+            "township-rs",
+            "--input",
+            "a.txt",
+            "--parallel-files",
+            "--output",
+            "out.csv",
+        ]);
+        assert_eq!(opts.output_paths().unwrap(), [(Format::Csv, PathBuf::from("out.csv"))]);
+    }
+
+    #[test]
+    fn only_text_outputs_can_be_gzipped() {
+        let opts = Opts::from_iter(&["township-rs", "--format", "parquet", "--output", "out.parquet.gz"]);
+        assert_eq!(
+            opts.output_paths().unwrap_err().to_string(),
+            "Parquet output can't be gzipped, so out.parquet.gz shouldn't end in .gz"
+        );
+
+        let opts = Opts::from_iter(&[
+            "township-rs",
+            "--format",
+            "json-full,parquet",
+            "--output",
+            "out.json.gz",
+            "--output",
+            "out.parquet",
+        ]);
+        assert_eq!(
+            opts.output_paths().unwrap(),
+            [
+                (Format::JsonFull, PathBuf::from("out.json.gz")),
+                (Format::Parquet, PathBuf::from("out.parquet"))
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_timeouts_are_rejected() {
+        let opts = Opts::from_iter(&["township-rs", "--timeout-connect=-1"]);
+        assert_eq!(
+            opts.client().unwrap_err().to_string(),
+            "--timeout-connect must be a non-negative number of seconds"
+        );
+    }
+}
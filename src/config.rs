@@ -0,0 +1,191 @@
+use anyhow::{anyhow, bail, Error};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// Config keys holding secrets, and the environment variable each one stands in for.
+const CREDENTIAL_KEYS: [(&str, &str); 3] = [
+    ("api_key", "API_KEY"),
+    ("google_api_key", "GOOGLE_API_KEY"),
+    ("nominatim_email", "NOMINATIM_EMAIL"),
+];
+
+/// Settings loaded from a `--config` TOML file.
+///
+/// Keys are the long command line options without their dashes, so `max-retries = 5` (or `max_retries = 5`) works
+/// like `--max-retries 5`. Options given on the command line win over the file. The file can also hold credentials
+/// as `api_key`, `google_api_key` or `nominatim_email`. Any string value can pull in an environment variable with
+/// `${NAME}`, which keeps secrets out of the file itself:
+///
+/// ```toml
+/// provider = ["google", "nominatim"]
+/// max-retries = 5
+/// api_key = "${GOOGLE_API_KEY}"
+/// ```
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Each option with the values to pass it, in the file's order.
+    options: Vec<(String, Vec<String>)>,
+    /// Credentials, keyed by the environment variable they replace.
+    pub credentials: HashMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| anyhow!("Couldn't read the config file {}: {}", path.display(), e))?;
+        let table: toml::value::Table = toml::from_str(&contents)
+            .map_err(|e| anyhow!("Couldn't parse the config file {}: {}", path.display(), e))?;
+
+        let mut config = Config::default();
+        for (key, value) in table {
+            let values = match value {
+                toml::Value::Array(items) => items.iter().map(|item| scalar(&key, item)).collect::<Result<_, _>>()?,
+                // `flag = false` is the same as leaving the flag out.
+                toml::Value::Boolean(false) => continue,
+                toml::Value::Boolean(true) => vec![],
+                value => vec![scalar(&key, &value)?],
+            };
+
+            if let Some((_, variable)) = CREDENTIAL_KEYS.iter().find(|(name, _)| *name == key) {
+                let value = values
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("Config value for '{}' must be a string", key))?;
+                config.credentials.insert(variable.to_string(), value);
+            } else {
+                config.options.push((key.replace('_', "-"), values));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Puts the file's options in front of the command line `args`, skipping any option the command line already
+    /// sets.
+    pub fn merge_args(&self, args: Vec<OsString>) -> Vec<OsString> {
+        let given = |option: &str| {
+            let flag = format!("--{}", option);
+            let prefix = format!("{}=", flag);
+            args.iter()
+                .filter_map(|arg| arg.to_str())
+                .any(|arg| arg == flag || arg.starts_with(&prefix))
+        };
+
+        let mut merged: Vec<OsString> = args.iter().take(1).cloned().collect();
+        for (option, values) in &self.options {
+            if given(option) {
+                continue;
+            }
+
+            let flag = OsString::from(format!("--{}", option));
+            if values.is_empty() {
+                merged.push(flag.clone());
+            }
+            for value in values {
+                merged.push(flag.clone());
+                merged.push(OsString::from(value));
+            }
+        }
+        merged.extend(args.into_iter().skip(1));
+
+        merged
+    }
+}
+
+/// Turns a single TOML value into the text you'd type on the command line, expanding `${NAME}`s in strings.
+fn scalar(key: &str, value: &toml::Value) -> Result<String, Error> {
+    match value {
+        toml::Value::String(text) => interpolate(key, text, |name| std::env::var(name).ok()),
+        toml::Value::Integer(number) => Ok(number.to_string()),
+        toml::Value::Float(number) => Ok(number.to_string()),
+        toml::Value::Boolean(flag) => Ok(flag.to_string()),
+        _ => Err(anyhow!(
+            "Config value for '{}' must be a string, number, boolean or list",
+            key
+        )),
+    }
+}
+
+/// Replaces every `${NAME}` in `text` with what `lookup` finds for `NAME`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let lookup = |name: &str| Some(name.to_lowercase()).filter(|_| name == "SET");
+/// assert_eq!(interpolate("api_key", "key-${SET}", lookup)?, "key-set");
+/// assert!(interpolate("api_key", "${UNSET}", lookup).is_err());
+/// ```
+fn interpolate(key: &str, text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, Error> {
+    let mut expanded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => bail!("Config value for '{}' has an unclosed '${{'", key),
+        };
+
+        let name = &rest[start + 2..end];
+        match lookup(name) {
+            Some(value) => expanded.push_str(&value),
+            None => bail!("Config value for '{}' uses ${{{}}}, but {} isn't set", key, name, name),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        Some("secret".to_string()).filter(|_| name == "GOOGLE_API_KEY")
+    }
+
+    #[test]
+    fn interpolates_set_variables() {
+        assert_eq!(interpolate("api_key", "${GOOGLE_API_KEY}", lookup).unwrap(), "secret");
+        assert_eq!(
+            interpolate("api_key", "key-${GOOGLE_API_KEY}-${GOOGLE_API_KEY}!", lookup).unwrap(),
+            "key-secret-secret!"
+        );
+        assert_eq!(interpolate("api_key", "plain", lookup).unwrap(), "plain");
+    }
+
+    #[test]
+    fn names_the_unset_variable() {
+        let error = interpolate("api_key", "${MISSING_KEY}", lookup).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config value for 'api_key' uses ${MISSING_KEY}, but MISSING_KEY isn't set"
+        );
+        assert!(interpolate("api_key", "${GOOGLE_API_KEY", lookup).is_err());
+    }
+
+    #[test]
+    fn loads_interpolated_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("township.toml");
+        fs::write(&path, "api_key = \"${TOWNSHIP_RS_CONFIG_TEST_KEY}\"\nmax-retries = 5\n").unwrap();
+
+        let error = Config::load(&path).unwrap_err();
+        assert!(error.to_string().contains("TOWNSHIP_RS_CONFIG_TEST_KEY isn't set"));
+
+        std::env::set_var("TOWNSHIP_RS_CONFIG_TEST_KEY", "from-env");
+        let config = Config::load(&path).unwrap();
+        std::env::remove_var("TOWNSHIP_RS_CONFIG_TEST_KEY");
+        assert_eq!(config.credentials["API_KEY"], "from-env");
+        assert_eq!(
+            config.merge_args(vec!["township-rs".into()]),
+            ["township-rs", "--max-retries", "5"]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Error};
+
+/// The secrets each provider needs, gathered in one place so every provider pulls only its own.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    /// From `GOOGLE_API_KEY`, or the older `API_KEY` if that isn't set.
+    pub google_api_key: Option<String>,
+    /// From `NOMINATIM_EMAIL`. Optional, but Nominatim's usage policy asks heavy users to identify themselves.
+    pub nominatim_email: Option<String>,
+}
+
+impl Credentials {
+    /// Builds credentials from any source of environment-style variables.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// let credentials = Credentials::from_lookup(|name| match name {
+    ///     "API_KEY" => Some(String::from("old")),
+    ///     "GOOGLE_API_KEY" => Some(String::from("new")),
+    ///     _ => None,
+    /// });
+    /// assert_eq!(credentials.google_api_key.as_deref(), Some("new"));
+    /// ```
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Credentials {
+        let non_empty = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+
+        Credentials {
+            google_api_key: non_empty("GOOGLE_API_KEY").or_else(|| non_empty("API_KEY")),
+            nominatim_email: non_empty("NOMINATIM_EMAIL"),
+        }
+    }
+
+    /// The Google API key, or an error explaining how to provide one.
+    pub fn google_api_key(&self) -> Result<String, Error> {
+        self.google_api_key
+            .clone()
+            .ok_or_else(|| anyhow!("The Google provider needs an API key, set GOOGLE_API_KEY (or API_KEY)"))
+    }
+}
+
+/// Reads a secret from the OS keyring, or `None` if there's no entry for `service` and `account`.
+pub fn from_keyring(service: &str, account: &str) -> Result<Option<String>, Error> {
+    read_entry(&keyring::Entry::new(service, account)?)
+}
+
+/// Stores a secret in the OS keyring, replacing any already there for `service` and `account`.
+pub fn store_in_keyring(service: &str, account: &str, secret: &str) -> Result<(), Error> {
+    store_entry(&keyring::Entry::new(service, account)?, secret)
+}
+
+fn read_entry(entry: &keyring::Entry) -> Result<Option<String>, Error> {
+    match entry.get_password() {
+        Ok(secret) => Ok(Some(secret).filter(|s| !s.trim().is_empty())),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn store_entry(entry: &keyring::Entry, secret: &str) -> Result<(), Error> {
+    entry
+        .set_password(secret)
+        .map_err(|e| anyhow!("Couldn't store the secret in the keyring: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyring::credential::{CredentialApi, CredentialBuilderApi};
+    use keyring::mock::{self, MockCredential};
+    use std::any::Any;
+    use std::sync::Mutex;
+
+    fn lookup<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn google_key_prefers_the_new_name() {
+        let credentials = Credentials::from_lookup(lookup(&[("API_KEY", "old"), ("GOOGLE_API_KEY", "new")]));
+        assert_eq!(credentials.google_api_key().unwrap(), "new");
+
+        let credentials = Credentials::from_lookup(lookup(&[("API_KEY", "old"), ("GOOGLE_API_KEY", " ")]));
+        assert_eq!(credentials.google_api_key().unwrap(), "old");
+    }
+
+    #[test]
+    fn each_provider_gets_its_own_credentials() {
+        let credentials = Credentials::from_lookup(lookup(&[("NOMINATIM_EMAIL", "me@example.com")]));
+        assert_eq!(credentials.nominatim_email.as_deref(), Some("me@example.com"));
+        assert_eq!(
+            credentials.google_api_key().unwrap_err().to_string(),
+            "The Google provider needs an API key, set GOOGLE_API_KEY (or API_KEY)"
+        );
+    }
+
+    #[test]
+    fn config_file_wins_over_the_environment() {
+        use crate::cli::Opts;
+        use structopt::StructOpt;
+
+        let mut opts = Opts::from_iter(&["township-rs"]);
+        opts.config_credentials
+            .insert(String::from("GOOGLE_API_KEY"), String::from("from-config"));
+        assert_eq!(opts.credentials().google_api_key().unwrap(), "from-config");
+    }
+
+    // The mock keyring keeps a secret only in the entry it was set on, so each test reads back through one entry.
+    // It's built directly rather than through the default builder, which the `Opts` tests below replace.
+    fn mock_entry() -> keyring::Entry {
+        let credential = mock::default_credential_builder()
+            .build(None, "township-rs", "google-api-key")
+            .unwrap();
+        keyring::Entry::new_with_credential(credential)
+    }
+
+    /// Secrets for `SharedCredential`, by service and account, kept for the whole test run.
+    static SHARED_SECRETS: Mutex<Vec<(String, String, String)>> = Mutex::new(Vec::new());
+
+    /// A keyring entry that, unlike the mock's, is seen again by every entry made for the same service and account.
+    /// `Opts::credentials` makes its own entry, so this is how a test gets a secret to it.
+    #[derive(Debug)]
+    struct SharedCredential {
+        service: String,
+        account: String,
+    }
+
+    impl CredentialApi for SharedCredential {
+        fn set_password(&self, password: &str) -> keyring::Result<()> {
+            let mut secrets = SHARED_SECRETS.lock().unwrap();
+            secrets.retain(|(service, account, _)| (service, account) != (&self.service, &self.account));
+            secrets.push((self.service.clone(), self.account.clone(), password.to_string()));
+            Ok(())
+        }
+
+        fn get_password(&self) -> keyring::Result<String> {
+            SHARED_SECRETS
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(service, account, _)| (service, account) == (&self.service, &self.account))
+                .map(|(_, _, secret)| secret.clone())
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn delete_password(&self) -> keyring::Result<()> {
+            unimplemented!()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[derive(Debug)]
+    struct SharedCredentialBuilder;
+
+    impl CredentialBuilderApi for SharedCredentialBuilder {
+        fn build(&self, _target: Option<&str>, service: &str, user: &str) -> keyring::Result<Box<keyring::Credential>> {
+            Ok(Box::new(SharedCredential {
+                service: service.to_string(),
+                account: user.to_string(),
+            }))
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    /// Parses `Opts` with `--keyring` under `account`, with a config file key to fall back on.
+    fn keyring_opts(account: &str) -> crate::cli::Opts {
+        use structopt::StructOpt;
+
+        keyring::set_default_credential_builder(Box::new(SharedCredentialBuilder));
+        let mut opts = crate::cli::Opts::from_iter(&["township-rs", "--keyring", "--keyring-account", account]);
+        opts.config_credentials
+            .insert(String::from("GOOGLE_API_KEY"), String::from("from-config"));
+        opts
+    }
+
+    #[test]
+    fn keyring_key_wins_over_the_config_file() {
+        let opts = keyring_opts("present");
+        store_in_keyring("township-rs", "present", "from-keyring").unwrap();
+        assert_eq!(opts.credentials().google_api_key().unwrap(), "from-keyring");
+    }
+
+    #[test]
+    fn missing_keyring_key_falls_back_to_the_config_file() {
+        let opts = keyring_opts("absent");
+        assert_eq!(opts.credentials().google_api_key().unwrap(), "from-config");
+    }
+
+    #[test]
+    fn stored_key_reads_back() {
+        let entry = mock_entry();
+        assert_eq!(read_entry(&entry).unwrap(), None);
+
+        store_entry(&entry, "secret").unwrap();
+        assert_eq!(read_entry(&entry).unwrap().as_deref(), Some("secret"));
+
+        store_entry(&entry, "replaced").unwrap();
+        assert_eq!(read_entry(&entry).unwrap().as_deref(), Some("replaced"));
+    }
+
+    #[test]
+    fn blank_key_reads_as_missing() {
+        let entry = mock_entry();
+        store_entry(&entry, "  ").unwrap();
+        assert_eq!(read_entry(&entry).unwrap(), None);
+    }
+
+    #[test]
+    fn keyring_errors_are_reported() {
+        let entry = mock_entry();
+        let credential: &MockCredential = entry.get_credential().downcast_ref().unwrap();
+        credential.set_error(keyring::Error::NoStorageAccess(anyhow!("locked").into()));
+
+        let error = store_entry(&entry, "secret").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Couldn't store the secret in the keyring"));
+    }
+
+    #[test]
+    fn store_key_parses_as_a_subcommand() {
+        use crate::cli::{Command, Opts};
+        use structopt::StructOpt;
+
+        let opts = Opts::from_iter(&["township-rs", "--keyring-account", "work", "store-key"]);
+        assert!(matches!(opts.command, Some(Command::StoreKey)));
+        assert_eq!(opts.keyring_account, "work");
+    }
+}
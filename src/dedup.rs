@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How aggressively addresses are normalized before deciding whether two of them are the same.
+///
+/// Each policy includes the ones before it: `CaseInsensitive` also trims, and `Alnum` also ignores case.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalization {
+    /// Only byte-for-byte identical addresses are merged.
+    Exact,
+    /// Leading and trailing whitespace is ignored.
+    Trim,
+    /// Case is ignored.
+    CaseInsensitive,
+    /// Punctuation is ignored and runs of whitespace are collapsed, so "123 Main St." matches "123 main st".
+    Alnum,
+}
+
+impl Normalization {
+    /// Reduces `address` to the key duplicates are matched on.
+    pub fn normalize(&self, address: &str) -> String {
+        match self {
+            Normalization::Exact => address.to_string(),
+            Normalization::Trim => address.trim().to_string(),
+            Normalization::CaseInsensitive => address.trim().to_lowercase(),
+            Normalization::Alnum => address
+                .to_lowercase()
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(Normalization::Exact),
+            "trim" => Ok(Normalization::Trim),
+            "case-insensitive" => Ok(Normalization::CaseInsensitive),
+            "alnum" => Ok(Normalization::Alnum),
+            other => Err(anyhow!(
+                "Unknown normalization '{}', expected 'exact', 'trim', 'case-insensitive' or 'alnum'",
+                other
+            )),
+        }
+    }
+}
+
+/// A list of addresses with the duplicates collapsed.
+#[derive(Debug, PartialEq)]
+pub struct Deduped {
+    /// The first occurrence of every distinct address, in input order. These are the ones sent to the API.
+    pub unique: Vec<String>,
+    /// For every input address, the index of its entry in `unique`.
+    pub mapping: Vec<usize>,
+}
+
+/// Collapses `addresses` that are the same under `policy`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let addresses = vec![String::from("123 Main St."), String::from("123 main st")];
+/// let deduped = dedup(&addresses, Normalization::Alnum);
+/// assert_eq!(deduped.unique, vec!["123 Main St."]);
+/// assert_eq!(deduped.mapping, vec![0, 0]);
+/// ```
+pub fn dedup(addresses: &[String], policy: Normalization) -> Deduped {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut unique = vec![];
+    let mapping = addresses
+        .iter()
+        .map(|address| {
+            *seen.entry(policy.normalize(address)).or_insert_with(|| {
+                unique.push(address.clone());
+                unique.len() - 1
+            })
+        })
+        .collect();
+
+    Deduped { unique, mapping }
+}
+
+/// Input addresses that were collapsed into one, for `--dedup-report`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Group {
+    /// The key the addresses were matched on.
+    pub normalized: String,
+    /// The one address that was looked up for all of them.
+    pub geocoded: String,
+    /// Every input that mapped to it, exactly as given and in input order.
+    pub inputs: Vec<String>,
+}
+
+/// Lists the groups of `inputs` that `deduped` collapsed into a single address, leaving out the ones that stood
+/// alone.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let inputs = vec![String::from("1 Oak St."), String::from("2 Elm St"), String::from(" 1 oak st")];
+/// let groups = groups(&inputs, &dedup(&inputs, Normalization::Alnum), Normalization::Alnum);
+/// assert_eq!(groups.len(), 1);
+/// assert_eq!(groups[0].normalized, "1 oak st");
+/// assert_eq!(groups[0].inputs, vec!["1 Oak St.", " 1 oak st"]);
+/// ```
+pub fn groups(inputs: &[String], deduped: &Deduped, policy: Normalization) -> Vec<Group> {
+    let mut groups: Vec<Group> = deduped
+        .unique
+        .iter()
+        .map(|address| Group {
+            normalized: policy.normalize(address),
+            geocoded: address.clone(),
+            inputs: vec![],
+        })
+        .collect();
+    for (input, &unique_index) in inputs.iter().zip(&deduped.mapping) {
+        groups[unique_index].inputs.push(input.clone());
+    }
+
+    groups.retain(|group| group.inputs.len() > 1);
+    groups
+}
+
+/// Writes the `groups` of `inputs` that were collapsed to `path` as JSON.
+pub fn write_report(path: &Path, inputs: &[String], deduped: &Deduped, policy: Normalization) -> Result<(), Error> {
+    let groups = groups(inputs, deduped, policy);
+    fs::write(path, serde_json::to_string_pretty(&groups)? + "\n")?;
+    log!(
+        "Wrote {} groups of duplicate addresses to {}",
+        groups.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near_duplicates() -> Vec<String> {
+        [
+            "123 Main St",
+            "123 Main St",
+            " 123 Main St ",
+            "123 MAIN ST",
+            "123 Main St.",
+            "456 Oak Ave",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn exact_only_merges_identical_addresses() {
+        let deduped = dedup(&near_duplicates(), Normalization::Exact);
+        assert_eq!(deduped.unique.len(), 5);
+        assert_eq!(deduped.mapping, [0, 0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn trim_ignores_surrounding_whitespace() {
+        let deduped = dedup(&near_duplicates(), Normalization::Trim);
+        assert_eq!(
+            deduped.unique,
+            ["123 Main St", "123 MAIN ST", "123 Main St.", "456 Oak Ave"]
+        );
+        assert_eq!(deduped.mapping, [0, 0, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn case_insensitive_ignores_case() {
+        let deduped = dedup(&near_duplicates(), Normalization::CaseInsensitive);
+        assert_eq!(deduped.unique, ["123 Main St", "123 Main St.", "456 Oak Ave"]);
+        assert_eq!(deduped.mapping, [0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn alnum_ignores_punctuation() {
+        let deduped = dedup(&near_duplicates(), Normalization::Alnum);
+        assert_eq!(deduped.unique, ["123 Main St", "456 Oak Ave"]);
+        assert_eq!(deduped.mapping, [0, 0, 0, 0, 0, 1]);
+    }
+
+    fn groups_for(inputs: &[String], policy: Normalization) -> Vec<Group> {
+        groups(inputs, &dedup(inputs, policy), policy)
+    }
+
+    #[test]
+    fn report_groups_follow_the_policy() {
+        let inputs = near_duplicates();
+
+        assert_eq!(
+            groups_for(&inputs, Normalization::CaseInsensitive),
+            [Group {
+                normalized: String::from("123 main st"),
+                geocoded: String::from("123 Main St"),
+                inputs: vec![
+                    String::from("123 Main St"),
+                    String::from("123 Main St"),
+                    String::from(" 123 Main St "),
+                    String::from("123 MAIN ST"),
+                ],
+            }]
+        );
+
+        let groups = groups_for(&inputs, Normalization::Exact);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].inputs, ["123 Main St", "123 Main St"]);
+
+        let groups = groups_for(&inputs, Normalization::Alnum);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].inputs.len(), 5);
+        assert!(!groups[0].inputs.contains(&String::from("456 Oak Ave")));
+    }
+
+    #[test]
+    fn report_is_written_as_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.json");
+        let inputs = near_duplicates();
+        let deduped = dedup(&inputs, Normalization::Trim);
+
+        write_report(&path, &inputs, &deduped, Normalization::Trim).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!([{
+                "normalized": "123 Main St",
+                "geocoded": "123 Main St",
+                "inputs": ["123 Main St", "123 Main St", " 123 Main St "],
+            }])
+        );
+    }
+}
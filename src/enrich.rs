@@ -0,0 +1,196 @@
+use crate::cli::Opts;
+use crate::input::{self, AddressColumn};
+use crate::output::FailureReport;
+use crate::summary::Summary;
+use crate::township::get_township;
+use crate::{get_geo_data, Job};
+use anyhow::{anyhow, Error};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The column `--enrich` fills in, the same one a normal run writes.
+const TOWNSHIP_COLUMN: &str = "Township";
+
+/// A data row of the CSV being enriched, along with where its raw bytes sit in the file.
+struct Row {
+    /// The row's fields, without the line break that ends it.
+    span: Range<usize>,
+    record: csv::ByteRecord,
+}
+
+/// Geocodes the rows of the CSV at `path` whose Township is empty and writes their townships back into the file.
+///
+/// Rows that already have a township, or that still don't get one, are copied over byte for byte, so quoting, line
+/// endings and every other column stay exactly as they were. The file is replaced in one go once every row is done.
+pub async fn run(opts: &Opts, path: &Path) -> Result<(), Error> {
+    let contents = fs::read(path).map_err(|e| anyhow!("Couldn't read {}: {}", path.display(), e))?;
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents.as_slice());
+
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| anyhow!("No '{}' column in {}", name, path.display()))
+    };
+    let township_index = column(TOWNSHIP_COLUMN)?;
+    let address_index = match opts.address_column() {
+        AddressColumn::Index(index) => index,
+        AddressColumn::Name(name) => column(&name)?,
+    };
+
+    let is_line_break = |byte: &u8| *byte == b'\r' || *byte == b'\n';
+    let mut rows = vec![];
+    let mut start = reader.position().byte() as usize;
+    let mut record = csv::ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        let end = reader.position().byte() as usize;
+        // A row's span can begin with the tail of the previous row's line break and end with part of its own.
+        let raw = &contents[start..end];
+        let leading = raw.iter().take_while(|b| is_line_break(b)).count();
+        let trailing = raw[leading..].iter().rev().take_while(|b| is_line_break(b)).count();
+        rows.push(Row {
+            span: start + leading..end - trailing,
+            record: record.clone(),
+        });
+        start = end;
+    }
+
+    let empty: Vec<usize> = (0..rows.len())
+        .filter(|&n| {
+            rows[n]
+                .record
+                .get(township_index)
+                .is_none_or(|t| t.trim_ascii().is_empty())
+        })
+        .collect();
+    log!(
+        "{} of {} rows in {} have no township",
+        empty.len(),
+        rows.len(),
+        path.display()
+    );
+
+    let inputs = empty
+        .iter()
+        .map(|&n| {
+            let address = rows[n].record.get(address_index).unwrap_or_default();
+            input::join_lines(&String::from_utf8_lossy(address))
+        })
+        .collect();
+    let job = Job::prepare(opts, None, inputs)?;
+    input::confirm_run(job.to_geocode.len(), opts.confirm_above, opts.yes)?;
+
+    let client = opts.client()?;
+    let geocoders = opts.geocoders(&opts.credentials())?;
+    let cache = Mutex::new(crate::Cache::open(opts.cache.as_deref())?);
+    let budget = opts.retry_budget()?;
+    let precedence = opts.precedence();
+    let results = get_geo_data(
+        &client,
+        &job.to_geocode,
+        &geocoders,
+        &precedence,
+        &cache,
+        opts.max_retries,
+        opts.batch_size,
+        &budget,
+    )
+    .await?;
+
+    let mut failures = FailureReport::create(&job.failures, job.failures_plain.as_deref())?;
+    let mut summary = Summary::default();
+    let mut filled = vec![None; rows.len()];
+    for (&n, (input, &unique_index)) in empty.iter().zip(job.inputs.iter().zip(&job.deduped.mapping)) {
+        let found = match &results[unique_index] {
+            Ok(found) => get_township(&found.response, &precedence).map_err(|miss| miss.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        match found {
+            Ok(found) => {
+                log!("Township for {} is {}", found.formatted_address, found.township);
+                summary.resolved(found.source.as_deref());
+                filled[n] = Some(with_township(
+                    &contents[rows[n].span.clone()],
+                    township_index,
+                    &found.township,
+                ));
+            }
+            Err(reason) => {
+                failures.record(input, &reason)?;
+                summary.failed();
+            }
+        }
+    }
+    failures.finish()?;
+
+    // Everything between the filled in rows, line breaks included, is copied as is.
+    let mut enriched = vec![];
+    let mut copied = 0;
+    for (row, filled) in rows.iter().zip(filled) {
+        if let Some(bytes) = filled {
+            enriched.extend_from_slice(&contents[copied..row.span.start]);
+            enriched.extend(bytes);
+            copied = row.span.end;
+        }
+    }
+    enriched.extend_from_slice(&contents[copied..]);
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(format!(".{}.tmp", std::process::id()));
+    fs::write(&temporary, enriched)?;
+    fs::rename(&temporary, path)?;
+    log!("Filled in {} townships in {}", summary.resolved, path.display());
+    summary.print(&opts.configured_township_types());
+
+    Ok(())
+}
+
+/// Puts `township` in field `index` of a raw CSV row, leaving the bytes of every other field as they were.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert_eq!(with_township(b"1,\"2 Oak St\",,x", 2, "Bethel"), b"1,\"2 Oak St\",Bethel,x");
+/// assert_eq!(with_township(b"1,\"2 Oak St\"", 2, "Bethel, OH"), b"1,\"2 Oak St\",\"Bethel, OH\"");
+/// ```
+fn with_township(row: &[u8], index: usize, township: &str) -> Vec<u8> {
+    let mut fields = vec![];
+    let (mut start, mut quoted) = (0, false);
+    for (i, &byte) in row.iter().enumerate() {
+        match byte {
+            b'"' => quoted = !quoted,
+            b',' if !quoted => {
+                fields.push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(start..row.len());
+
+    let township = if township.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", township.replace('"', "\"\""))
+    } else {
+        township.to_string()
+    };
+
+    let mut bytes = row.to_vec();
+    match fields.get(index) {
+        Some(field) => {
+            bytes.splice(field.clone(), township.into_bytes());
+        }
+        None => {
+            bytes.extend(b",".repeat(index + 1 - fields.len()));
+            bytes.extend(township.into_bytes());
+        }
+    }
+    bytes
+}
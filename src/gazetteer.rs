@@ -0,0 +1,85 @@
+use anyhow::{bail, Error};
+use std::fs;
+use std::path::Path;
+
+/// A reference list of canonical township names that extracted townships are fuzzy-matched against, so variations
+/// like "Springfield Twp" line up with "Springfield Township".
+#[derive(Debug)]
+pub struct Gazetteer {
+    names: Vec<String>,
+    /// The lowest Jaro-Winkler similarity, between 0 and 1, that still counts as a match.
+    threshold: f64,
+}
+
+impl Gazetteer {
+    /// Reads a gazetteer file with one canonical name per line.
+    pub fn load(path: &Path, threshold: f64) -> Result<Gazetteer, Error> {
+        let names: Vec<String> = fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if names.is_empty() {
+            bail!("The gazetteer at {} has no names in it", path.display());
+        }
+
+        Ok(Gazetteer::new(names, threshold))
+    }
+
+    pub fn new(names: Vec<String>, threshold: f64) -> Gazetteer {
+        Gazetteer { names, threshold }
+    }
+
+    /// The canonical name closest to `township` along with its similarity, whether or not it clears the threshold.
+    pub fn closest(&self, township: &str) -> Option<(&str, f64)> {
+        let township = township.to_lowercase();
+        self.names
+            .iter()
+            .map(|name| (name.as_str(), strsim::jaro_winkler(&township, &name.to_lowercase())))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The canonical name for `township`, or `None` if nothing is similar enough.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// let gazetteer = Gazetteer::new(vec![String::from("Springfield Township")], 0.85);
+    /// assert_eq!(gazetteer.canonicalize("Springfield Twp"), Some("Springfield Township"));
+    /// ```
+    pub fn canonicalize(&self, township: &str) -> Option<&str> {
+        self.closest(township)
+            .filter(|(_, similarity)| *similarity >= self.threshold)
+            .map(|(name, _)| name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gazetteer() -> Gazetteer {
+        let names = ["Bethel Township", "Springfield Township", "Mad River Township"];
+        Gazetteer::new(names.iter().map(|n| n.to_string()).collect(), 0.85)
+    }
+
+    #[test]
+    fn abbreviation_matches_the_canonical_name() {
+        assert_eq!(
+            gazetteer().canonicalize("Springfield Twp"),
+            Some("Springfield Township")
+        );
+        assert_eq!(gazetteer().canonicalize("bethel township"), Some("Bethel Township"));
+    }
+
+    #[test]
+    fn nothing_close_enough_is_no_match() {
+        let gazetteer = gazetteer();
+        assert_eq!(gazetteer.canonicalize("Columbus"), None);
+        assert!(gazetteer.closest("Columbus").is_some());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::credentials::Credentials;
+use crate::retry::{RetryPolicy, RETRYABLE_HTTP_CODES, RETRYABLE_STATUSES};
+use crate::{AddressComponent, AddressResult, GeoDataAddress, Geometry, Location};
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+const GOOGLE_BASE_URL: &str = "https://maps.googleapis.com/maps/api/geocode/json";
+const NOMINATIM_BASE_URL: &str = "https://nominatim.openstreetmap.org/search";
+
+/// Settings shared by every provider.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderSettings {
+    /// Replaces the provider's default endpoint, e.g. to go through a proxy.
+    pub base_url: Option<String>,
+    pub region: Option<String>,
+    pub language: Option<String>,
+    /// The largest response body that will be read before giving up on an address.
+    pub max_response_bytes: usize,
+    /// Replaces the provider's own retryable statuses.
+    pub retryable_statuses: Option<Vec<String>>,
+    /// Replaces the default retryable HTTP status codes, `RETRYABLE_HTTP_CODES`.
+    pub retryable_http_codes: Option<Vec<u16>>,
+}
+
+impl ProviderSettings {
+    /// The retry policy for a provider whose own retryable statuses are `statuses`, unless they're overridden.
+    fn retry_policy(&self, statuses: &[&str]) -> RetryPolicy {
+        RetryPolicy {
+            statuses: self
+                .retryable_statuses
+                .clone()
+                .unwrap_or_else(|| statuses.iter().map(|s| s.to_string()).collect()),
+            http_codes: self
+                .retryable_http_codes
+                .clone()
+                .unwrap_or_else(|| RETRYABLE_HTTP_CODES.to_vec()),
+        }
+    }
+}
+
+/// Returned when a response body is bigger than `ProviderSettings::max_response_bytes`. Not worth retrying.
+#[derive(Debug)]
+pub struct ResponseTooLarge {
+    pub limit: usize,
+}
+
+impl fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Response body exceeded the {} byte limit", self.limit)
+    }
+}
+
+impl std::error::Error for ResponseTooLarge {}
+
+/// Returned when a response body isn't JSON, or isn't in the shape expected. The same request would get the same
+/// body, so it's not worth retrying.
+#[derive(Debug)]
+pub struct UnreadableResponse {
+    pub message: String,
+}
+
+impl fmt::Display for UnreadableResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for UnreadableResponse {}
+
+/// Reads a JSON response body, refusing to buffer more than `limit` bytes of it.
+async fn read_json<T: DeserializeOwned>(mut response: reqwest::Response, limit: usize) -> Result<T, Error> {
+    if response.content_length().is_some_and(|length| length > limit as u64) {
+        return Err(ResponseTooLarge { limit }.into());
+    }
+
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await.map_err(reqwest::Error::without_url)? {
+        if body.len() + chunk.len() > limit {
+            return Err(ResponseTooLarge { limit }.into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    serde_json::from_slice(&body).map_err(|e| describe_bad_body(&body, e))
+}
+
+/// How much of an unreadable response body to quote in the error.
+const BODY_SNIPPET_CHARS: usize = 200;
+
+/// Explains a body that didn't deserialize, quoting the start of it. A body that isn't JSON at all is usually an
+/// HTML error or login page from a proxy, rather than the provider changing its response format.
+fn describe_bad_body(body: &[u8], error: serde_json::Error) -> Error {
+    let text = String::from_utf8_lossy(body);
+    let mut snippet: String = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if snippet.chars().count() > BODY_SNIPPET_CHARS {
+        snippet = snippet.chars().take(BODY_SNIPPET_CHARS).collect::<String>() + "...";
+    }
+
+    let message = if serde_json::from_slice::<serde_json::Value>(body).is_err() {
+        format!(
+            "Received a non-JSON response, check the base URL and any proxy in between: {}",
+            snippet
+        )
+    } else {
+        format!(
+            "Received JSON in an unexpected shape ({}), the provider may have changed its format: {}",
+            error, snippet
+        )
+    };
+
+    UnreadableResponse { message }.into()
+}
+
+/// An HTTP request to a provider, either with the address in the query string or in a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    Get {
+        url: String,
+        query: Vec<(String, String)>,
+    },
+    /// For providers and batch endpoints that only take a JSON body. `query` is still appended to the URL, which
+    /// is where some of them want an API key.
+    Post {
+        url: String,
+        query: Vec<(String, String)>,
+        body: serde_json::Value,
+    },
+}
+
+impl Request {
+    /// Sends the request, failing on any non-success HTTP status.
+    ///
+    /// Errors leave out the URL, whose query can hold an API key, since they end up in the log and the failures
+    /// report.
+    pub async fn send(self, client: &reqwest::Client) -> Result<reqwest::Response, Error> {
+        let request = match self {
+            Request::Get { url, query } => client.get(&url).query(&query),
+            Request::Post { url, query, body } => client.post(&url).query(&query).json(&body),
+        };
+
+        let response = request.send().await.map_err(reqwest::Error::without_url)?;
+        Ok(response.error_for_status().map_err(reqwest::Error::without_url)?)
+    }
+}
+
+/// A geocoding backend that turns an address into a `GeoDataAddress`.
+///
+/// Providers that don't speak Google's response format translate their results into it, so the rest of the
+/// pipeline only ever deals with one shape.
+#[async_trait]
+pub trait Geocoder: Send + Sync {
+    /// A short, stable name for the provider, used in cache keys.
+    fn name(&self) -> &'static str;
+
+    /// Query parameters besides the address that change what the provider returns. Part of the cache key, so a
+    /// result fetched with one set is never served for another.
+    fn cache_params(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// Which of the provider's failures are worth another attempt.
+    fn retry_policy(&self) -> RetryPolicy;
+
+    /// Makes a single request for `address`.
+    async fn geocode(&self, client: &reqwest::Client, address: &str) -> Result<GeoDataAddress, Error>;
+
+    /// Geocodes several addresses at once, returning one result per address in the same order. Providers that take
+    /// batches override this, and the rest just make a request per address.
+    async fn geocode_batch(
+        &self,
+        client: &reqwest::Client,
+        addresses: &[String],
+    ) -> Vec<Result<GeoDataAddress, Error>> {
+        let mut results = vec![];
+        for address in addresses {
+            results.push(self.geocode(client, address).await);
+        }
+        results
+    }
+}
+
+/// The Google GeoCode API.
+pub struct Google {
+    api_key: String,
+    settings: ProviderSettings,
+}
+
+impl Google {
+    pub fn new(api_key: String, settings: ProviderSettings) -> Google {
+        Google { api_key, settings }
+    }
+}
+
+#[async_trait]
+impl Geocoder for Google {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.settings.retry_policy(&RETRYABLE_STATUSES)
+    }
+
+    fn cache_params(&self) -> Vec<(String, String)> {
+        let mut params = vec![];
+        if let Some(region) = &self.settings.region {
+            params.push(("region".to_string(), region.clone()));
+        }
+        if let Some(language) = &self.settings.language {
+            params.push(("language".to_string(), language.clone()));
+        }
+        params
+    }
+
+    async fn geocode(&self, client: &reqwest::Client, address: &str) -> Result<GeoDataAddress, Error> {
+        let mut query = vec![
+            ("key".to_string(), self.api_key.clone()),
+            ("address".to_string(), address.to_string()),
+        ];
+        query.extend(self.cache_params());
+        let request = Request::Get {
+            url: self.settings.base_url.as_deref().unwrap_or(GOOGLE_BASE_URL).to_string(),
+            query,
+        };
+
+        read_json(request.send(client).await?, self.settings.max_response_bytes).await
+    }
+}
+
+/// OpenStreetMap's Nominatim search API.
+pub struct Nominatim {
+    email: Option<String>,
+    settings: ProviderSettings,
+}
+
+impl Nominatim {
+    pub fn new(email: Option<String>, settings: ProviderSettings) -> Nominatim {
+        Nominatim { email, settings }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NominatimPlace {
+    place_id: u64,
+    lat: String,
+    lon: String,
+    display_name: String,
+    #[serde(default)]
+    addresstype: Option<String>,
+    #[serde(default)]
+    importance: Option<f64>,
+    #[serde(default)]
+    address: HashMap<String, String>,
+}
+
+/// Nominatim address keys and the Google component type each one corresponds to, most specific first.
+const NOMINATIM_COMPONENTS: [(&str, &str); 12] = [
+    ("house_number", "street_number"),
+    ("road", "route"),
+    ("city", "locality"),
+    ("town", "locality"),
+    ("village", "locality"),
+    ("hamlet", "locality"),
+    ("township", "administrative_area_level_3"),
+    ("municipality", "administrative_area_level_3"),
+    ("county", "administrative_area_level_2"),
+    ("state", "administrative_area_level_1"),
+    ("country", "country"),
+    ("postcode", "postal_code"),
+];
+
+impl NominatimPlace {
+    /// Translates a Nominatim place into Google's result shape.
+    fn into_address_result(self) -> Result<AddressResult, Error> {
+        let address = &self.address;
+        let address_components = NOMINATIM_COMPONENTS
+            .iter()
+            .filter_map(|(key, kind)| {
+                let long_name = address.get(*key)?.clone();
+                let short_name = match *key {
+                    // "ISO3166-2-lvl4" looks like "US-OH", Google's short name for a state is just "OH".
+                    "state" => address
+                        .get("ISO3166-2-lvl4")
+                        .and_then(|code| code.split('-').nth(1))
+                        .map(str::to_string),
+                    "country" => address.get("country_code").map(|code| code.to_uppercase()),
+                    _ => None,
+                };
+
+                Some(AddressComponent {
+                    short_name: short_name.unwrap_or_else(|| long_name.clone()),
+                    long_name,
+                    types: vec![kind.to_string(), "political".to_string()],
+                })
+            })
+            .collect();
+
+        Ok(AddressResult {
+            address_components,
+            formatted_address: self.display_name,
+            geometry: Some(Geometry {
+                location: Location {
+                    lat: self.lat.parse()?,
+                    lng: self.lon.parse()?,
+                },
+                extra: Default::default(),
+            }),
+            place_id: Some(self.place_id.to_string()),
+            partial_match: false,
+            types: self.addresstype.into_iter().collect(),
+            confidence: self.importance,
+            extra: Default::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl Geocoder for Nominatim {
+    fn name(&self) -> &'static str {
+        "nominatim"
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        // Nominatim has no statuses of its own, only HTTP errors.
+        self.settings.retry_policy(&[])
+    }
+
+    fn cache_params(&self) -> Vec<(String, String)> {
+        match &self.settings.language {
+            Some(language) => vec![("accept-language".to_string(), language.clone())],
+            None => vec![],
+        }
+    }
+
+    async fn geocode(&self, client: &reqwest::Client, address: &str) -> Result<GeoDataAddress, Error> {
+        let mut query: Vec<(String, String)> = [
+            ("q", address),
+            ("format", "jsonv2"),
+            ("addressdetails", "1"),
+            ("limit", "1"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        query.extend(self.cache_params());
+        query.extend(self.email.iter().map(|email| ("email".to_string(), email.clone())));
+        let request = Request::Get {
+            url: self
+                .settings
+                .base_url
+                .as_deref()
+                .unwrap_or(NOMINATIM_BASE_URL)
+                .to_string(),
+            query,
+        };
+
+        let response = request.send(client).await?;
+        let places: Vec<NominatimPlace> = read_json(response, self.settings.max_response_bytes).await?;
+
+        let results = places
+            .into_iter()
+            .map(NominatimPlace::into_address_result)
+            .collect::<Result<Vec<_>, _>>()?;
+        let status = if results.is_empty() { "ZERO_RESULTS" } else { "OK" };
+
+        Ok(GeoDataAddress {
+            status: status.to_string(),
+            results,
+            extra: Default::default(),
+        })
+    }
+}
+
+/// A self-hosted or proxy endpoint that takes the address as a JSON body, `{"address": "..."}`, and answers in
+/// Google's response format. It has no default endpoint, so it needs a `--base-url`.
+///
+/// With `--batch-size`, it sends `{"addresses": [...]}` instead and expects a JSON array with one response per
+/// address, in the same order.
+pub struct JsonPost {
+    settings: ProviderSettings,
+}
+
+impl JsonPost {
+    pub fn new(settings: ProviderSettings) -> Result<JsonPost, Error> {
+        if settings.base_url.is_none() {
+            return Err(anyhow!(
+                "The json-post provider has no default endpoint, set one with --base-url"
+            ));
+        }
+
+        Ok(JsonPost { settings })
+    }
+
+    async fn post_batch(&self, client: &reqwest::Client, addresses: &[String]) -> Result<Vec<GeoDataAddress>, Error> {
+        let mut body = serde_json::json!({ "addresses": addresses });
+        for (name, value) in self.cache_params() {
+            body[name] = serde_json::Value::String(value);
+        }
+        let request = Request::Post {
+            url: self.settings.base_url.clone().unwrap_or_default(),
+            query: vec![],
+            body,
+        };
+
+        // The limit is per address, so a batch gets as much room as its addresses would have had on their own.
+        let limit = self.settings.max_response_bytes.saturating_mul(addresses.len());
+        let responses: Vec<GeoDataAddress> = read_json(request.send(client).await?, limit).await?;
+        if responses.len() != addresses.len() {
+            return Err(anyhow!(
+                "The batch response had {} results for {} addresses",
+                responses.len(),
+                addresses.len()
+            ));
+        }
+
+        Ok(responses)
+    }
+}
+
+#[async_trait]
+impl Geocoder for JsonPost {
+    fn name(&self) -> &'static str {
+        "json-post"
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        // It answers in Google's format, statuses included.
+        self.settings.retry_policy(&RETRYABLE_STATUSES)
+    }
+
+    fn cache_params(&self) -> Vec<(String, String)> {
+        let mut params = vec![];
+        if let Some(region) = &self.settings.region {
+            params.push(("region".to_string(), region.clone()));
+        }
+        if let Some(language) = &self.settings.language {
+            params.push(("language".to_string(), language.clone()));
+        }
+        params
+    }
+
+    async fn geocode(&self, client: &reqwest::Client, address: &str) -> Result<GeoDataAddress, Error> {
+        let mut body = serde_json::json!({ "address": address });
+        for (name, value) in self.cache_params() {
+            body[name] = serde_json::Value::String(value);
+        }
+        let request = Request::Post {
+            url: self.settings.base_url.clone().unwrap_or_default(),
+            query: vec![],
+            body,
+        };
+
+        read_json(request.send(client).await?, self.settings.max_response_bytes).await
+    }
+
+    async fn geocode_batch(
+        &self,
+        client: &reqwest::Client,
+        addresses: &[String],
+    ) -> Vec<Result<GeoDataAddress, Error>> {
+        match self.post_batch(client, addresses).await {
+            Ok(responses) => responses.into_iter().map(Ok).collect(),
+            // Every address in the batch failed the same way.
+            Err(e) => addresses.iter().map(|_| Err(anyhow!("{}", e))).collect(),
+        }
+    }
+}
+
+/// Builds the provider named `name`, handing it its own credentials.
+pub fn build(name: &str, settings: ProviderSettings, credentials: &Credentials) -> Result<Box<dyn Geocoder>, Error> {
+    match name.to_lowercase().as_str() {
+        "google" => Ok(Box::new(Google::new(credentials.google_api_key()?, settings))),
+        "nominatim" => Ok(Box::new(Nominatim::new(credentials.nominatim_email.clone(), settings))),
+        "json-post" => Ok(Box::new(JsonPost::new(settings)?)),
+        other => Err(anyhow!(
+            "Unknown provider '{}', expected 'google', 'nominatim' or 'json-post'",
+            other
+        )),
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, bail, Error};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Where to find the addresses in a CSV file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressColumn {
+    /// The column with this header.
+    Name(String),
+    /// The column at this zero-based position, for files without a header row.
+    Index(usize),
+}
+
+/// Asks for the path to a file containing a list of addresses.
+pub fn prompt_path() -> Result<PathBuf, Error> {
+    let mut path = String::new();
+
+    log_inline!("Please input path: ")?;
+    io::stdin().read_line(&mut path)?;
+
+    Ok(normalize_path(&path))
+}
+
+/// Asks for a secret, like an API key, reading it from stdin so it never shows up in the process list or shell
+/// history.
+pub fn prompt_secret(prompt: &str) -> Result<String, Error> {
+    let mut secret = String::new();
+
+    log_inline!("{}: ", prompt)?;
+    io::stdin().read_line(&mut secret)?;
+
+    match secret.trim() {
+        "" => bail!("No {} given", prompt.to_lowercase()),
+        secret => Ok(secret.to_string()),
+    }
+}
+
+/// Cleans up a path typed or pasted at the prompt: trims it, unescapes shell-escaped spaces (`\ `) left by
+/// drag-and-drop or tab completion, and expands a leading `~` to the home directory.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert_eq!(normalize_path(" My\\ Lists/ohio.txt \n"), PathBuf::from("My Lists/ohio.txt"));
+/// // With HOME=/home/jackson
+/// assert_eq!(normalize_path("~/ohio.txt"), PathBuf::from("/home/jackson/ohio.txt"));
+/// ```
+pub fn normalize_path(path: &str) -> PathBuf {
+    let path = path.trim().replace("\\ ", " ");
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Reads the addresses in the file at `path`.
+///
+/// Files ending in `.csv` are parsed as CSV, taking each address from `column`. Anything else is treated as plain
+/// text with one address per line. With `skip_header`, a first line that looks like a column header rather than an
+/// address is left out.
+pub fn read_file(path: &Path, column: &AddressColumn, skip_header: bool) -> Result<Vec<String>, Error> {
+    let addresses = if is_csv(path) {
+        read_csv_addresses(path, column)?
+    } else {
+        let contents = fs::read_to_string(path)?;
+        let mut lines: Vec<String> = contents.split('\n').map(|s| s.to_string()).collect();
+        if skip_header && lines.first().is_some_and(|line| looks_like_header(line)) {
+            log!(
+                "Skipping the first line of {}, it looks like a header: {}",
+                path.display(),
+                lines[0].trim()
+            );
+            lines.remove(0);
+        }
+        lines
+    };
+    log!("Successfully read your list of addresses from {}!", path.display());
+
+    Ok(addresses)
+}
+
+/// Words a header line for a list of addresses is made of, like `Street Address` or `Full Address`.
+const HEADER_WORDS: [&str; 10] = [
+    "address",
+    "addresses",
+    "street",
+    "full",
+    "mailing",
+    "location",
+    "site",
+    "property",
+    "line",
+    "1",
+];
+
+/// Whether `line` is a header someone pasted along with their addresses, which is made of nothing but header words
+/// and mentions an address or location.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert!(looks_like_header("Address"));
+/// assert!(looks_like_header(" STREET ADDRESS\r"));
+/// assert!(looks_like_header("Address Line 1"));
+/// assert!(!looks_like_header("1 Address Way"));
+/// assert!(!looks_like_header("123 Main St"));
+/// ```
+fn looks_like_header(line: &str) -> bool {
+    let line = line.trim().to_lowercase();
+    let words: Vec<&str> = line
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    !words.is_empty()
+        && words.iter().all(|word| HEADER_WORDS.contains(word))
+        && words
+            .iter()
+            .any(|word| word.starts_with("address") || *word == "location")
+        && words[0] != "1"
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// Reads the addresses in `column` of a CSV file. A file read by column index is assumed to have no header row.
+///
+/// Quoted fields may span several lines, so a multi-line address stays a single record. Its line breaks are
+/// replaced with spaces before it's handed to the API.
+fn read_csv_addresses(path: &Path, column: &AddressColumn) -> Result<Vec<String>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(matches!(column, AddressColumn::Name(_)))
+        .flexible(true)
+        .from_path(path)?;
+    let index = match column {
+        AddressColumn::Index(index) => *index,
+        AddressColumn::Name(name) => reader
+            .headers()?
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| anyhow!("No '{}' column in {}", name, path.display()))?,
+    };
+
+    let mut addresses = vec![];
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        match record.get(index) {
+            Some(address) => addresses.push(join_lines(address)),
+            None => bail!("Row {} of {} has no column {}", line + 1, path.display(), index),
+        }
+    }
+
+    Ok(addresses)
+}
+
+/// Replaces the line breaks inside an address with single spaces.
+pub fn join_lines(address: &str) -> String {
+    address
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Collapses every run of whitespace, including tabs and non-breaking spaces, into a single space and trims the ends.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert_eq!(normalize_whitespace(" 123\u{a0}Main  St,\tSpringfield "), "123 Main St, Springfield");
+/// ```
+pub fn normalize_whitespace(address: &str) -> String {
+    address.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Adds `prefix` and `suffix` around a trimmed address, leaving blank addresses blank so they don't geocode to just
+/// the region the suffix names. Trimming first keeps stray whitespace from stopping duplicates collapsing.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert_eq!(augment("123 Main St ", None, Some(", Springfield, OH")), "123 Main St, Springfield, OH");
+/// assert_eq!(augment(" ", None, Some(", Springfield, OH")), " ");
+/// ```
+pub fn augment(address: &str, prefix: Option<&str>, suffix: Option<&str>) -> String {
+    if address.trim().is_empty() {
+        return address.to_string();
+    }
+
+    format!(
+        "{}{}{}",
+        prefix.unwrap_or_default(),
+        address.trim(),
+        suffix.unwrap_or_default()
+    )
+}
+
+/// Asks before geocoding more than `threshold` addresses, since big runs cost real money.
+///
+/// Passing `assume_yes` skips the question. Without a terminal to ask on, the run is aborted rather than left
+/// waiting for an answer that will never come.
+pub fn confirm_run(count: usize, threshold: usize, assume_yes: bool) -> Result<(), Error> {
+    if assume_yes || count <= threshold {
+        return Ok(());
+    }
+
+    if !io::stdin().is_terminal() {
+        bail!(
+            "Refusing to geocode {} addresses (more than {}) without confirmation, pass --yes to go ahead",
+            count,
+            threshold
+        );
+    }
+
+    let mut answer = String::new();
+    log_inline!("About to geocode {} addresses. Continue? [y/N] ", count)?;
+    io::stdin().read_line(&mut answer)?;
+
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => bail!("Aborted, nothing was geocoded"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to a file called `name` in a new temporary directory.
+    fn input_file(name: &str, contents: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn quoted_newline_is_one_address() {
+        let (_dir, path) = input_file(
+            "input.csv",
+            "Address,Notes\n\"123 Main St\nSpringfield, OH\",first\n456 Oak Ave,second\n",
+        );
+
+        let addresses = read_file(&path, &AddressColumn::Name(String::from("Address")), false).unwrap();
+        assert_eq!(addresses, ["123 Main St Springfield, OH", "456 Oak Ave"]);
+    }
+
+    #[test]
+    fn headerless_csv_is_read_by_index() {
+        let (_dir, path) = input_file("input.csv", "1,123 Main St,OH\n2,456 Oak Ave,OH\n");
+
+        let addresses = read_file(&path, &AddressColumn::Index(1), false).unwrap();
+        assert_eq!(addresses, ["123 Main St", "456 Oak Ave"]);
+
+        let error = read_file(&path, &AddressColumn::Index(3), false).unwrap_err();
+        assert!(error.to_string().starts_with("Row 1 of "), "{}", error);
+    }
+
+    #[test]
+    fn messy_whitespace_is_cleaned_up() {
+        assert_eq!(
+            normalize_whitespace("  123\u{a0}Main \t St,\n\nSpringfield,   OH  "),
+            "123 Main St, Springfield, OH"
+        );
+        assert_eq!(normalize_whitespace(" \t "), "");
+    }
+
+    #[test]
+    fn pasted_paths_are_cleaned_up() {
+        assert_eq!(
+            normalize_path(" My\\ Lists/ohio\\ 2024.txt \n"),
+            PathBuf::from("My Lists/ohio 2024.txt")
+        );
+        assert_eq!(normalize_path("lists/ohio.txt"), PathBuf::from("lists/ohio.txt"));
+    }
+
+    #[test]
+    fn leading_tilde_is_the_home_directory() {
+        let home = PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).unwrap());
+        assert_eq!(normalize_path("~/ohio.txt"), home.join("ohio.txt"));
+        assert_eq!(normalize_path("~/My\\ Lists/ohio.txt"), home.join("My Lists/ohio.txt"));
+        assert_eq!(normalize_path("~"), home);
+        // Someone else's home isn't looked up, and a tilde anywhere else is just part of the name.
+        assert_eq!(normalize_path("~jackson/ohio.txt"), PathBuf::from("~jackson/ohio.txt"));
+        assert_eq!(normalize_path("lists/~ohio.txt"), PathBuf::from("lists/~ohio.txt"));
+    }
+
+    #[test]
+    fn header_line_is_skipped_when_asked() {
+        let (_dir, path) = input_file("input.txt", "Address\n1 Main St\n2 Oak Ave");
+        let column = AddressColumn::Name(String::from("Address"));
+
+        assert_eq!(read_file(&path, &column, true).unwrap(), ["1 Main St", "2 Oak Ave"]);
+        assert_eq!(
+            read_file(&path, &column, false).unwrap(),
+            ["Address", "1 Main St", "2 Oak Ave"]
+        );
+
+        let (_dir, path) = input_file("input.txt", "1 Address Way\nAddress");
+        assert_eq!(read_file(&path, &column, true).unwrap(), ["1 Address Way", "Address"]);
+    }
+
+    #[test]
+    fn header_words_are_recognized() {
+        for header in ["Address", "address", " STREET ADDRESS\r", "Address Line 1", "Location"] {
+            assert!(looks_like_header(header), "{:?}", header);
+        }
+        for address in ["1 Address Way", "123 Main St", "Street", ""] {
+            assert!(!looks_like_header(address), "{:?}", address);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set when results are written to stdout, so the log goes to stderr instead of ending up mixed into them.
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends everything logged from here on to stderr.
+pub fn to_stderr() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn is_stderr() -> bool {
+    TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// Prints a line of progress or diagnostics like `println!`, on stderr once `to_stderr` has been called.
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if $crate::log::is_stderr() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// Like `log!`, but without ending the line, for prompts. Flushes, so the text shows before any input is read.
+macro_rules! log_inline {
+    ($($arg:tt)*) => {{
+        use std::io::Write;
+        if $crate::log::is_stderr() {
+            eprint!($($arg)*);
+            std::io::stderr().flush()
+        } else {
+            print!($($arg)*);
+            std::io::stdout().flush()
+        }
+    }};
+}
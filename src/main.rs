@@ -1,51 +1,125 @@
-use anyhow::Error;
-use csv::Writer;
+#[macro_use]
+mod log;
+
+mod cache;
+mod check;
+mod cli;
+mod config;
+mod credentials;
+mod dedup;
+mod enrich;
+mod gazetteer;
+mod geocoder;
+mod input;
+mod manifest;
+mod output;
+mod preview;
+mod progress;
+mod retry;
+mod sink;
+mod summary;
+#[cfg(test)]
+mod testing;
+mod township;
+mod validate;
+mod verify;
+
+use anyhow::{anyhow, Error};
+use cache::{Cache, CacheKey};
+use chrono::Utc;
+use cli::{Command, Opts};
 use dotenv::dotenv;
+use futures_util::future::try_join_all;
+use gazetteer::Gazetteer;
+use geocoder::Geocoder;
+use manifest::{InputFile, Manifest};
+use output::{FailureReport, Format};
+use progress::Eta;
+use retry::RetryBudget;
 use serde::{Deserialize, Serialize};
-use std::io::Write;
-use std::{env, fs, io};
+use sink::Destination;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+use std::{fs, io};
+use summary::Summary;
+use township::{get_all_townships, get_township, Precedence};
+
+type JsonMap = serde_json::Map<String, serde_json::Value>;
+
+/// Sent with every request. Nominatim's usage policy requires an identifying user agent.
+const USER_AGENT: &str = concat!("township-rs/", env!("CARGO_PKG_VERSION"));
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A geocoding response. Fields the tool doesn't use are kept in `extra` so the response can be written back out
+/// without losing anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct GeoDataAddress {
     status: String,
     results: Vec<AddressResult>,
+    #[serde(flatten)]
+    extra: JsonMap,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AddressResult {
     address_components: Vec<AddressComponent>,
     formatted_address: String,
+    #[serde(default)]
+    geometry: Option<Geometry>,
+    #[serde(default)]
+    place_id: Option<String>,
+    /// Set when the provider couldn't match the whole input address, which usually means a poor result.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    partial_match: bool,
+    /// What kind of place the result is, e.g. `street_address` for a precise match or `locality` for a fuzzy one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    types: Vec<String>,
+    /// How sure the provider is of the result, for providers that say. Nominatim's `importance`, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    #[serde(flatten)]
+    extra: JsonMap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Geometry {
+    location: Location,
+    #[serde(flatten)]
+    extra: JsonMap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Location {
+    lat: f64,
+    lng: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AddressComponent {
     long_name: String,
     short_name: String,
     types: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct AddressOutput {
-    address: String,
-    township: String,
-}
-
-/// Gets a path to a file containing a list of addresses separated by a newline.
-fn read_addresses() -> Result<Vec<String>, Error> {
-    let mut path = String::new();
-
-    print!("Please input path: ");
-    io::stdout().flush()?;
-    io::stdin().read_line(&mut path)?;
-
-    let contents = fs::read_to_string(path.trim())?;
-    let addresses: Vec<String> = contents.split("\n").map(|s| s.to_string()).collect();
-    println!("Successfully read your list of addresses!");
-
-    Ok(addresses)
+/// A response along with the name of the provider that gave it.
+#[derive(Debug, Clone)]
+struct Geocoded {
+    provider: &'static str,
+    response: GeoDataAddress,
 }
 
-/// Uses the Google GeoCode API to find geo data from a list of addresses.
+/// Uses a chain of `Geocoder`s to find geo data from a list of addresses.
+///
+/// Each address is tried against the providers in order, falling back to the next one when a provider errors or
+/// comes back without a township, going by `precedence`. When none of them finds one, the first response with any
+/// results is kept, if there was one. Responses already in `cache` are reused without a request, and new ones are added
+/// to it. Transient failures, as each provider's `RetryPolicy` defines them, are retried with exponential backoff,
+/// up to `max_retries` times per address and for as long as the shared `budget` allows. Returns one result
+/// per address, in order, so a single bad address doesn't sink the whole run.
+///
+/// With a `batch_size` above 1, the first provider is sent the uncached addresses that many at a time before the
+/// loop, and only what the batches didn't settle is requested one address at a time.
 ///
 /// # Examples
 ///
@@ -53,105 +127,750 @@ fn read_addresses() -> Result<Vec<String>, Error> {
 ///
 /// ```
 /// let fake_addr = String::from("123 Fake Addr Rd, Springfield, OH");
-/// let geo_data = get_geo_data(vec![fake_addr]);
+/// let google: Box<dyn Geocoder> = Box::new(Google::new(api_key, ProviderSettings::default()));
+/// let cache = Mutex::new(Cache::default());
+/// let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+/// let geo_data = get_geo_data(&client, &[fake_addr], &[google], &precedence, &cache, 3, 1, &RetryBudget::new(None));
 /// ```
-async fn get_geo_data(addresses: Vec<String>) -> Result<Vec<GeoDataAddress>, Error> {
-    let api_key = env::var("API_KEY")?;
-    let base_url = "https://maps.googleapis.com/maps/api/geocode/json";
-    let mut results: Vec<GeoDataAddress> = vec![];
-    let client = reqwest::Client::new();
+#[allow(clippy::too_many_arguments)]
+async fn get_geo_data(
+    client: &reqwest::Client,
+    addresses: &[String],
+    geocoders: &[Box<dyn Geocoder>],
+    precedence: &Precedence<'_>,
+    cache: &Mutex<Cache>,
+    max_retries: u32,
+    batch_size: usize,
+    budget: &RetryBudget,
+) -> Result<Vec<Result<Geocoded, Error>>, Error> {
+    let mut results: Vec<Result<Geocoded, Error>> = vec![];
 
-    println!("Starting data gathering...");
+    log!("Starting data gathering...");
 
+    let mut batched = match geocoders.first() {
+        Some(geocoder) if batch_size > 1 => {
+            fetch_batches(client, geocoder.as_ref(), cache, addresses, batch_size).await
+        }
+        _ => HashMap::new(),
+    };
+
+    let mut eta = Eta::default();
     for (i, address) in addresses.iter().enumerate() {
-        println!("Processing #{} - {}", i + 1, address);
+        match eta.remaining(addresses.len() - i) {
+            Some(left) => log!(
+                "Processing #{} of {} - {} (about {} left)",
+                i + 1,
+                addresses.len(),
+                address,
+                progress::format_duration(left)
+            ),
+            None => log!("Processing #{} of {} - {}", i + 1, addresses.len(), address),
+        }
+        let started = Instant::now();
 
-        let response: GeoDataAddress = client
-            .get(base_url)
-            .query(&[("key", &api_key), ("address", &address)])
-            .send()
+        let mut result = Err(anyhow!("No providers configured"));
+        // The first response with results, in case no provider finds a township.
+        let mut answered = None;
+        for (n, geocoder) in geocoders.iter().enumerate() {
+            let name = geocoder.name();
+            let prefetched = if n == 0 { batched.remove(address) } else { None };
+            result = geocode_with_retries(
+                client,
+                geocoder.as_ref(),
+                cache,
+                address,
+                prefetched,
+                max_retries,
+                budget,
+            )
             .await?
-            .json()
-            .await?;
+            .map(|response| Geocoded {
+                provider: name,
+                response,
+            });
+
+            let reason = match &result {
+                Ok(found) => match get_township(&found.response, precedence) {
+                    Ok(township) if township.source.is_some() => {
+                        answered = None;
+                        break;
+                    }
+                    Ok(_) => {
+                        answered.get_or_insert_with(|| found.clone());
+                        String::from("no township among its components")
+                    }
+                    Err(_) => found.response.status.clone(),
+                },
+                Err(e) => e.to_string(),
+            };
+            if let Some(next) = geocoders.get(n + 1) {
+                log!(
+                    "Falling back to {} for #{} after {}: {}",
+                    next.name(),
+                    i + 1,
+                    name,
+                    reason
+                );
+            }
+        }
 
-        results.push(response);
+        results.push(answered.map(Ok).unwrap_or(result));
+        eta.record(started.elapsed());
     }
 
     Ok(results)
 }
 
-/// Finds the correct township in a `GeoDataAddress`.
-///
-/// This function uses the first result in a given `GeoDataAddress`. It checks a couple different
-/// types of political entities, in the order of more to less specific. A locality takes precedence over a level 3
-/// area, which takes precedence over level 2. Returns an `Option<String>`, representing either the township or a failure to parse.
-///
-/// # Examples
+/// Requests the addresses `cache` doesn't have yet from `geocoder`, `batch_size` at a time, keyed by address.
 ///
-/// Basic usage:
+/// Anything a batch doesn't settle, whether the batch failed or the response is worth retrying, is left out, so the
+/// address is requested on its own like any other.
+async fn fetch_batches(
+    client: &reqwest::Client,
+    geocoder: &dyn Geocoder,
+    cache: &Mutex<Cache>,
+    addresses: &[String],
+    batch_size: usize,
+) -> HashMap<String, GeoDataAddress> {
+    let pending: Vec<String> = {
+        let cache = lock(cache);
+        addresses
+            .iter()
+            .filter(|address| cache.get(&CacheKey::new(geocoder, address)).is_none())
+            .cloned()
+            .collect()
+    };
+
+    let policy = geocoder.retry_policy();
+    let total = pending.len().div_ceil(batch_size);
+    let mut batched = HashMap::new();
+    for (n, batch) in pending.chunks(batch_size).enumerate() {
+        log!(
+            "Sending batch {} of {} ({} addresses) to {}",
+            n + 1,
+            total,
+            batch.len(),
+            geocoder.name()
+        );
+
+        let mut unsettled = vec![];
+        for (address, outcome) in batch.iter().zip(geocoder.geocode_batch(client, batch).await) {
+            match outcome {
+                Ok(response)
+                    if !policy.is_retryable_status(&response.status) && !retry::is_quota_exhausted(&response) =>
+                {
+                    batched.insert(address.clone(), response);
+                }
+                Ok(response) => unsettled.push(response.status),
+                Err(e) => unsettled.push(e.to_string()),
+            }
+        }
+        if let Some(reason) = unsettled.first() {
+            log!(
+                "{} addresses in batch {} will be requested on their own after: {}",
+                unsettled.len(),
+                n + 1,
+                reason
+            );
+        }
+    }
+
+    batched
+}
+
+/// Geocodes a single address with one provider, going through the cache and retrying transient failures. A
+/// `prefetched` response, one a batch already got, is cached and used instead of making a request. Only responses
+/// that settle the address are cached, see `cache::is_final`.
 ///
-/// ```
-/// let fake_addr = String::from("123 Fake Addr Rd, Springfield, OH");
-/// let geo_data = get_geo_data(vec![fake_addr]);
-/// let township = get_township(geo_data); // "Springfield City"
-/// ```
-fn get_township(result: GeoDataAddress) -> Option<(String, String)> {
-    if result.status != "OK" {
-        return None;
+/// The outer `Result` is for problems with the cache, the inner one for problems with the address.
+async fn geocode_with_retries(
+    client: &reqwest::Client,
+    geocoder: &dyn Geocoder,
+    cache: &Mutex<Cache>,
+    address: &str,
+    prefetched: Option<GeoDataAddress>,
+    max_retries: u32,
+    budget: &RetryBudget,
+) -> Result<Result<GeoDataAddress, Error>, Error> {
+    let key = CacheKey::new(geocoder, address);
+    let cached = lock(cache).get(&key).cloned();
+    if let Some(cached) = cached {
+        log!("Using cached {} response for {}", geocoder.name(), address);
+        return Ok(Ok(cached));
+    }
+
+    let result = match prefetched {
+        Some(response) => Ok(response),
+        None => fetch_with_retries(client, geocoder, address, max_retries, budget).await,
+    };
+    if let Some(response) = result.as_ref().ok().filter(|r| cache::is_final(r)) {
+        lock(cache).insert(key, response.clone())?;
     }
 
-    let mut township = String::new();
-    let first_result = result.results.get(0)?;
+    Ok(result)
+}
+
+/// Locks the shared cache. The lock is never held across an `.await`, so it's only ever poisoned by a panic that
+/// already took the run down.
+fn lock(cache: &Mutex<Cache>) -> MutexGuard<'_, Cache> {
+    // A task that panicked mid-insert can't leave the map half-updated, so the cache is still safe to use.
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Requests `address` from one provider, retrying transient failures with exponential backoff.
+async fn fetch_with_retries(
+    client: &reqwest::Client,
+    geocoder: &dyn Geocoder,
+    address: &str,
+    max_retries: u32,
+    budget: &RetryBudget,
+) -> Result<GeoDataAddress, Error> {
+    let policy = geocoder.retry_policy();
+    let mut attempt = 0;
+    loop {
+        let outcome = geocoder.geocode(client, address).await;
+        if let Some(response) = outcome.as_ref().ok().filter(|r| retry::is_quota_exhausted(r)) {
+            // Retrying straight away is pointless, and the response mustn't be taken as the address's answer.
+            let wait = match budget.try_wait_for_quota() {
+                Some(wait) => wait,
+                None => {
+                    return Err(anyhow!(
+                        "{}'s daily quota is used up ({})",
+                        geocoder.name(),
+                        response.status
+                    ))
+                }
+            };
+            let resume = chrono::Local::now() + chrono::Duration::from_std(wait)?;
+            log!(
+                "{}'s daily quota is used up ({}), pausing for {} until {} before resuming with {}",
+                geocoder.name(),
+                response.status,
+                progress::format_duration(wait),
+                resume.format("%Y-%m-%d %H:%M:%S"),
+                address
+            );
+            tokio::time::sleep(wait).await;
+            continue;
+        }
+        let reason = match &outcome {
+            Ok(response) if policy.is_retryable_status(&response.status) => response.status.clone(),
+            Ok(_) => return outcome,
+            Err(e) if !policy.is_retryable_error(e) => return outcome,
+            Err(e) => e.to_string(),
+        };
 
-    for addr_component in &first_result.address_components {
-        if addr_component.types.iter().any(|t| t == "locality") {
-            township = addr_component.long_name.clone();
-            break;
+        if attempt >= max_retries {
+            return Err(anyhow!("{} (gave up after {} retries)", reason, attempt));
         }
 
-        if addr_component.types.iter().any(|t| t == "administrative_area_level_3") {
-            township = addr_component.long_name.clone();
-            break;
+        if !budget.try_acquire() {
+            return Err(anyhow!("{} (global retry budget exhausted)", reason));
         }
 
-        if addr_component.types.iter().any(|t| t == "administrative_area_level_2") {
-            township = addr_component.long_name.clone();
-            break;
+        attempt += 1;
+        log!("Retrying {} ({}/{}) after: {}", address, attempt, max_retries, reason);
+        tokio::time::sleep(retry::backoff(attempt)).await;
+    }
+}
+
+/// Skips a record that couldn't be written with `--continue-on-write-error`, and fails the run otherwise.
+fn write_failed(opts: &Opts, summary: &mut Summary, input: &str, error: Error) -> Result<(), Error> {
+    if !opts.continue_on_write_error {
+        return Err(error);
+    }
+    log!("Skipping the record for {}, it couldn't be written: {}", input, error);
+    summary.skipped_writes += 1;
+
+    Ok(())
+}
+
+/// Removes the output left over from an earlier run, staying quiet if there isn't one.
+fn remove_previous_output(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path) {
+        Ok(()) => {
+            log!("Removed previous {}", path.display());
+            Ok(())
         }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
     }
+}
+
+/// Prints the township picked for each sampled address, or why there isn't one.
+fn print_preview(addresses: &[String], results: &[Result<Geocoded, Error>], precedence: &Precedence) {
+    let rows: Vec<Vec<String>> = addresses
+        .iter()
+        .zip(results)
+        .map(|(address, result)| {
+            let (township, detail) = match result {
+                Ok(found) => match get_township(&found.response, precedence) {
+                    Ok(found) => (found.township, found.formatted_address),
+                    Err(miss) => (String::new(), miss.to_string()),
+                },
+                Err(e) => (String::new(), e.to_string()),
+            };
+            vec![address.clone(), township, detail]
+        })
+        .collect();
+
+    preview::print_table(&["Input", "Township", "Formatted Address / Failure"], &rows);
+}
+
+/// Geocodes a single `address` and prints a breakdown of the result its township was picked from.
+async fn look_up(opts: &Opts, address: &str) -> Result<(), Error> {
+    let geocoders = opts.geocoders(&opts.credentials())?;
+    let cache = Mutex::new(Cache::open(opts.cache.as_deref())?);
+    let budget = opts.retry_budget()?;
+    let addresses = [address.to_string()];
+    let precedence = opts.precedence();
+    let mut results = get_geo_data(
+        &opts.client()?,
+        &addresses,
+        &geocoders,
+        &precedence,
+        &cache,
+        opts.max_retries,
+        opts.batch_size,
+        &budget,
+    )
+    .await?;
 
-    if township == "Springfield" {
-        township = String::from("Springfield City");
+    let found = results
+        .remove(0)
+        .map_err(|e| anyhow!("Couldn't geocode {}: {}", address, e))?;
+    let township =
+        get_township(&found.response, &precedence).map_err(|miss| anyhow!("No township for {}: {}", address, miss))?;
+    let record = output::OutputRecord::from_geo_data(address, &found.response, township.result_index);
+    let result = &found.response.results[township.result_index];
+
+    let postal_code = result
+        .address_components
+        .iter()
+        .find(|c| c.types.iter().any(|t| t == "postal_code"))
+        .map(|c| c.long_name.clone());
+    let coordinates = record.lat.zip(record.lng).map(|(lat, lng)| {
+        let precision = opts.coord_precision;
+        format!("{:.*}, {:.*}", precision, lat, precision, lng)
+    });
+    let mut quality = vec![];
+    if let Some(location_type) = result.geometry.as_ref().and_then(|g| g.extra.get("location_type")) {
+        quality.push(location_type.as_str().unwrap_or_default().to_string());
+    }
+    if let Some(confidence) = result.confidence {
+        quality.push(format!("confidence {}", confidence));
     }
+    if result.partial_match {
+        quality.push(String::from("partial match"));
+    }
+
+    let rows: Vec<Vec<String>> = vec![
+        ("Input", Some(address.to_string())),
+        ("Provider", Some(found.provider.to_string())),
+        ("Formatted Address", Some(township.formatted_address)),
+        ("Township", Some(township.township)),
+        ("Matched Type", township.source),
+        ("County", record.county),
+        ("State", record.state),
+        ("Postal Code", postal_code),
+        ("Lat/Lng", coordinates),
+        (
+            "Result Types",
+            Some(record.result_types.join(", ")).filter(|t| !t.is_empty()),
+        ),
+        ("Match Quality", Some(quality.join(", ")).filter(|q| !q.is_empty())),
+    ]
+    .into_iter()
+    .map(|(field, value)| vec![field.to_string(), value.unwrap_or_else(|| String::from("-"))])
+    .collect();
+    preview::print_table(&["Field", "Value"], &rows);
 
-    println!("Township for {} is {}", &first_result.formatted_address, township);
+    Ok(())
+}
 
-    return Some((first_result.formatted_address.clone(), township));
+/// What every job in a run shares, so concurrent jobs still go through one client, cache and retry budget.
+struct Shared {
+    client: reqwest::Client,
+    geocoders: Vec<Box<dyn Geocoder>>,
+    cache: Mutex<Cache>,
+    budget: RetryBudget,
+    gazetteer: Option<Gazetteer>,
+}
+
+/// One batch of input addresses and where its results go. Usually a whole run, or one file of it with
+/// `--parallel-files`.
+struct Job {
+    /// Set for per-file jobs, to tell their summaries apart.
+    label: Option<String>,
+    /// Each address exactly as it was given, for the output.
+    inputs: Vec<String>,
+    deduped: dedup::Deduped,
+    /// The unique addresses to look up, or a sample of them with `--preview`.
+    to_geocode: Vec<String>,
+    outputs: Vec<(Format, PathBuf)>,
+    failures: PathBuf,
+    failures_plain: Option<PathBuf>,
+    dedup_report: Option<PathBuf>,
+}
+
+impl Job {
+    /// Cleans up and deduplicates `inputs`, picking the addresses to look up.
+    fn prepare(opts: &Opts, label: Option<String>, inputs: Vec<String>) -> Result<Job, Error> {
+        // What gets geocoded, while `inputs` keeps each address as it was given.
+        let mut addrs = inputs.clone();
+        if opts.normalize_whitespace {
+            addrs = addrs.iter().map(|a| input::normalize_whitespace(a)).collect();
+        }
+        if opts.prepend_prefix.is_some() || opts.append_suffix.is_some() {
+            let (prefix, suffix) = (opts.prepend_prefix.as_deref(), opts.append_suffix.as_deref());
+            addrs = addrs.iter().map(|a| input::augment(a, prefix, suffix)).collect();
+        }
+
+        let deduped = dedup::dedup(&addrs, opts.dedup_normalization);
+        log!("{} unique addresses out of {}", deduped.unique.len(), addrs.len());
+
+        let to_geocode = match opts.preview {
+            Some(size) => preview::sample(&deduped.unique, size, opts.seed),
+            None => deduped.unique.clone(),
+        };
+
+        Ok(Job {
+            label,
+            inputs,
+            deduped,
+            to_geocode,
+            outputs: opts.output_paths()?,
+            failures: opts.failures.clone(),
+            failures_plain: opts.failures_plain.clone(),
+            dedup_report: opts.dedup_report.clone(),
+        })
+    }
+
+    /// Sends this job's output files next to the run's own, prefixed with `input`'s name.
+    fn for_input(mut self, input: &Path) -> Job {
+        for (_, path) in &mut self.outputs {
+            *path = cli::for_input(path, input);
+        }
+        self.failures = cli::for_input(&self.failures, input);
+        self.failures_plain = self.failures_plain.map(|path| cli::for_input(&path, input));
+        self.dedup_report = self.dedup_report.map(|path| cli::for_input(&path, input));
+        self
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     dotenv().ok();
+    let opts = Opts::load()?;
+    if opts.output_paths()?.iter().any(|(_, path)| path == Path::new("-")) {
+        // The results go to stdout, so the log can't.
+        log::to_stderr();
+    }
 
-    if let Err(_) = fs::remove_file("output.csv") {
-        println!("Tried to remove output.csv, but didn't exist. Fine!");
+    match opts.command {
+        Some(Command::StoreKey) => {
+            let key = input::prompt_secret("Google API key")?;
+            credentials::store_in_keyring(&opts.keyring_service, &opts.keyring_account, &key)?;
+            log!(
+                "Stored the API key in the keyring as {}/{}, use --keyring to read it",
+                opts.keyring_service,
+                opts.keyring_account
+            );
+            return Ok(());
+        }
+        Some(Command::Check) => return check::run(&opts).await,
+        None => {}
+    }
+    if opts.verify {
+        return verify::run(&opts).await;
+    }
+    if let [old, new] = opts.diff_cache.as_slice() {
+        return verify::diff(old, new, &opts.precedence());
+    }
+    if let Some(address) = &opts.address {
+        return look_up(&opts, address).await;
+    }
+    if let Some(path) = &opts.enrich {
+        return enrich::run(&opts, path).await;
     }
 
-    if let Ok(addrs) = read_addresses() {
-        let results = get_geo_data(addrs).await?;
-        println!("Successfully got {} results!", results.len());
+    let started = Utc::now();
+    let column = opts.address_column();
+    let paths = if opts.inputs.is_empty() {
+        vec![input::prompt_path()?]
+    } else {
+        opts.inputs.clone()
+    };
+    if opts.validate_only {
+        return validate::run(&opts, &paths);
+    }
+    let jobs = if opts.parallel_files {
+        let mut jobs = vec![];
+        for path in &paths {
+            let label = path.display().to_string();
+            jobs.push(
+                Job::prepare(
+                    &opts,
+                    Some(label),
+                    input::read_file(path, &column, opts.auto_skip_header)?,
+                )?
+                .for_input(path),
+            );
+        }
+        jobs
+    } else {
+        let mut inputs = vec![];
+        for path in &paths {
+            inputs.extend(input::read_file(path, &column, opts.auto_skip_header)?);
+        }
+        vec![Job::prepare(&opts, None, inputs)?]
+    };
+    let (addresses, unique_addresses) = jobs.iter().fold((0, 0), |(all, unique), job| {
+        (all + job.inputs.len(), unique + job.deduped.unique.len())
+    });
 
-        let mut csv_writer = Writer::from_path("output.csv")?;
-        csv_writer.write_record(&["Address", "Township"])?;
+    input::confirm_run(
+        jobs.iter().map(|job| job.to_geocode.len()).sum(),
+        opts.confirm_above,
+        opts.yes,
+    )?;
 
-        for addr in results {
-            if let Some((full_address, township)) = get_township(addr) {
-                csv_writer.write_record(&[full_address, township])?;
+    let shared = Shared {
+        client: opts.client()?,
+        geocoders: opts.geocoders(&opts.credentials())?,
+        cache: Mutex::new(Cache::open(opts.cache.as_deref())?),
+        budget: opts.retry_budget()?,
+        gazetteer: match &opts.gazetteer {
+            Some(path) => Some(Gazetteer::load(path, opts.gazetteer_threshold)?),
+            None => None,
+        },
+    };
+    // Only once the run is confirmed and set up, so a run that stops short leaves the last output alone.
+    if opts.preview.is_none() {
+        for (_, path) in jobs.iter().flat_map(|job| &job.outputs) {
+            if let Destination::File(path) = Destination::from_path(path)? {
+                remove_previous_output(&path)?;
             }
         }
+    }
+
+    let summaries = try_join_all(jobs.into_iter().map(|job| run_job(&opts, &shared, job))).await?;
 
-        csv_writer.flush()?;
+    if let Some(path) = opts.type_stats.as_ref().filter(|_| opts.preview.is_none()) {
+        summary::write_type_stats(&summaries, path.as_deref())?;
+    }
+
+    if let Some(path) = opts.manifest.as_ref().filter(|_| opts.preview.is_none()) {
+        Manifest {
+            version: env!("CARGO_PKG_VERSION"),
+            providers: shared.geocoders.iter().map(|g| g.name()).collect(),
+            inputs: paths.iter().map(|p| InputFile::hash(p)).collect::<Result<_, _>>()?,
+            addresses,
+            unique_addresses,
+            resolved: summaries.iter().map(|s| s.resolved).sum(),
+            failed: summaries.iter().map(|s| s.failed).sum(),
+            started,
+            finished: Utc::now(),
+            options: &opts,
+        }
+        .write(path)?;
     }
 
     Ok(())
 }
+
+/// Geocodes a job's addresses and writes out the results, or prints them with `--preview`.
+async fn run_job(opts: &Opts, shared: &Shared, job: Job) -> Result<Summary, Error> {
+    if let Some(path) = &job.dedup_report {
+        dedup::write_report(path, &job.inputs, &job.deduped, opts.dedup_normalization)?;
+    }
+
+    let mut order: Vec<usize> = (0..job.to_geocode.len()).collect();
+    if opts.shuffle_input {
+        preview::shuffle(&mut order, opts.seed);
+    }
+    let addresses: Vec<String> = order.iter().map(|&i| job.to_geocode[i].clone()).collect();
+    let precedence = opts.precedence();
+    let results = get_geo_data(
+        &shared.client,
+        &addresses,
+        &shared.geocoders,
+        &precedence,
+        &shared.cache,
+        opts.max_retries,
+        opts.batch_size,
+        &shared.budget,
+    )
+    .await?;
+    // Back in input order, which is the order everything is written in.
+    let mut results: Vec<(usize, Result<Geocoded, Error>)> = order.into_iter().zip(results).collect();
+    results.sort_by_key(|(i, _)| *i);
+    let results: Vec<Result<Geocoded, Error>> = results.into_iter().map(|(_, result)| result).collect();
+    log!("Successfully got {} results!", results.len());
+
+    if opts.preview.is_some() {
+        print_preview(&job.to_geocode, &results, &precedence);
+        return Ok(Summary::default());
+    }
+
+    let columns = output::Columns {
+        input_address: opts.input_address || opts.flatten_results,
+        formatted_address: !opts.no_formatted_address,
+        provider: shared.geocoders.len() > 1,
+        result_types: opts.result_types,
+        coordinates: Some(opts.coord_precision).filter(|_| opts.coordinates || opts.region_centroid),
+        wkt: Some(opts.coord_precision).filter(|_| opts.wkt),
+        coord_precision: opts.region_centroid,
+        confidence: opts.confidence,
+        canonical_township: opts.gazetteer.is_some(),
+        hierarchy: Some(opts.hierarchy_separator.clone()).filter(|_| opts.hierarchy_column),
+        renames: opts.renames.clone(),
+    };
+    let mut writer = output::open_all(&job.outputs, &columns, &shared.client)?;
+    let mut failures = FailureReport::create(&job.failures, job.failures_plain.as_deref())?;
+    let mut summary = Summary::default();
+
+    for (input, &unique_index) in job.inputs.iter().zip(&job.deduped.mapping) {
+        let (provider, addr) = match &results[unique_index] {
+            Ok(found) => (found.provider, &found.response),
+            Err(e) => {
+                failures.record(input, &e.to_string())?;
+                summary.failed();
+                continue;
+            }
+        };
+        summary.count_types(addr);
+        if let Err(e) = writer.write_response(input, addr) {
+            write_failed(opts, &mut summary, input, e)?;
+        }
+        let found = if opts.flatten_results {
+            get_all_townships(addr, &precedence)
+        } else {
+            get_township(addr, &precedence).map(|found| vec![found])
+        };
+
+        match found {
+            Ok(found) => {
+                // With `--flatten-results` an address can get several rows, but it's still one address resolved, or
+                // one failure when every row was rejected.
+                let mut resolved = false;
+                let mut rejected = None;
+                for found in found {
+                    let mut record = output::OutputRecord::from_geo_data(input, addr, found.result_index);
+
+                    if opts.drop_partial && addr.results[found.result_index].partial_match {
+                        rejected.get_or_insert_with(|| String::from("Partial match"));
+                        continue;
+                    }
+
+                    if opts.strip_plus_codes && township::is_plus_code(&addr.results[found.result_index]) {
+                        rejected.get_or_insert_with(|| String::from("Plus Code result"));
+                        continue;
+                    }
+
+                    if let Some(expected) = &opts.expect_state {
+                        let state = record.state.as_deref().unwrap_or_default();
+                        if !state.eq_ignore_ascii_case(expected) {
+                            let state = if state.is_empty() { "no state" } else { state };
+                            let reason = format!("Resolved to {}, expected {}", state, expected);
+                            log!("Warning: {} for {}", reason, found.formatted_address);
+                            summary.out_of_region += 1;
+
+                            if opts.strict_region {
+                                rejected.get_or_insert(reason);
+                                continue;
+                            }
+                        }
+                    }
+
+                    log!("Township for {} is {}", found.formatted_address, found.township);
+                    if opts.explain || opts.explain_json {
+                        let explanation = township::Explanation {
+                            input: input.clone(),
+                            query: job.to_geocode[unique_index].clone(),
+                            provider: provider.to_string(),
+                            provider_fallback: provider != shared.geocoders[0].name(),
+                            ..township::explain(addr, found.result_index, &precedence)
+                        };
+                        if opts.explain_json {
+                            log!("{}", serde_json::to_string(&explanation)?);
+                        } else {
+                            log_inline!("{}", explanation)?;
+                        }
+                    }
+                    if !resolved {
+                        summary.resolved(found.source.as_deref());
+                        resolved = true;
+                    }
+
+                    record.provider = provider.to_string();
+                    record.formatted_address = match &opts.trim_formatted_address {
+                        Some(suffix) => found
+                            .formatted_address
+                            .strip_suffix(suffix.as_str())
+                            .map(str::to_string)
+                            .unwrap_or(found.formatted_address),
+                        None => found.formatted_address,
+                    };
+                    record.township = found.township;
+
+                    if let Some(gazetteer) = &shared.gazetteer {
+                        record.canonical_township = gazetteer.canonicalize(&record.township).map(str::to_string);
+                        if record.canonical_township.is_none() {
+                            log!("No gazetteer match for township {}", record.township);
+                            summary.unmatched_canonical += 1;
+                        }
+                    }
+
+                    if let Err(e) = writer.write(&record, addr) {
+                        write_failed(opts, &mut summary, input, e)?;
+                    }
+                }
+
+                if let Some(reason) = rejected.filter(|_| !resolved) {
+                    failures.record(input, &reason)?;
+                    summary.failed();
+                }
+            }
+            Err(miss) => {
+                failures.record(input, &miss.to_string())?;
+                summary.failed();
+            }
+        }
+    }
+
+    writer.finish()?;
+    failures.finish()?;
+    if let Some(label) = &job.label {
+        log!("Results for {}:", label);
+    }
+    summary.print(&opts.configured_township_types());
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    #[test]
+    fn write_errors_are_skipped_and_counted_when_allowed() {
+        let opts = Opts::from_iter(&["township-rs", "--continue-on-write-error"]);
+        let mut summary = Summary::default();
+
+        write_failed(&opts, &mut summary, "1 Main St", anyhow!("disk full")).unwrap();
+        write_failed(&opts, &mut summary, "2 Oak Ave", anyhow!("disk full")).unwrap();
+        assert_eq!(summary.skipped_writes, 2);
+    }
+
+    #[test]
+    fn write_errors_fail_the_run_by_default() {
+        let opts = Opts::from_iter(&["township-rs"]);
+        let mut summary = Summary::default();
+
+        let error = write_failed(&opts, &mut summary, "1 Main St", anyhow!("disk full")).unwrap_err();
+        assert_eq!(error.to_string(), "disk full");
+        assert_eq!(summary.skipped_writes, 0);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::cli::Opts;
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A record of a run, written as JSON with `--manifest` so the run can be audited and repeated later.
+///
+/// Credentials never end up in it. They only ever live in the environment or in the config file's credential keys,
+/// and neither is copied over.
+#[derive(Debug, Serialize)]
+pub struct Manifest<'a> {
+    pub version: &'static str,
+    pub providers: Vec<&'static str>,
+    pub inputs: Vec<InputFile>,
+    pub addresses: usize,
+    pub unique_addresses: usize,
+    pub resolved: usize,
+    pub failed: usize,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// Every option the run used, including ones that came from `--config`.
+    pub options: &'a Opts,
+}
+
+/// An input file and the SHA-256 of its contents, to tell whether a rerun is really on the same data.
+#[derive(Debug, Serialize)]
+pub struct InputFile {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+impl InputFile {
+    pub fn hash(path: &Path) -> Result<InputFile, Error> {
+        let digest = Sha256::digest(fs::read(path)?);
+
+        Ok(InputFile {
+            path: path.to_path_buf(),
+            sha256: digest.iter().map(|b| format!("{:02x}", b)).collect(),
+        })
+    }
+}
+
+impl Manifest<'_> {
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        log!("Wrote the run manifest to {}", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,864 @@
+use crate::sink::{self, Destination, Sink};
+use crate::{AddressComponent, GeoDataAddress};
+use anyhow::{anyhow, bail, Error};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use csv::Writer;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How many rows the Parquet writer buffers before flushing them out as a row group.
+const PARQUET_BATCH_SIZE: usize = 1024;
+
+/// The file formats results can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    Csv,
+    Parquet,
+    /// A FeatureCollection with a point for every result.
+    #[serde(rename = "geojson")]
+    GeoJson,
+    /// Every provider response, untouched, keyed by input address.
+    JsonFull,
+}
+
+impl Format {
+    /// The file extension conventionally used for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+            Format::GeoJson => "geojson",
+            Format::JsonFull => "json",
+        }
+    }
+
+    /// Whether this format is text, and so worth gzipping. Parquet already compresses its columns with Snappy.
+    pub fn is_text(&self) -> bool {
+        !matches!(self, Format::Parquet)
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            "geojson" => Ok(Format::GeoJson),
+            "json-full" => Ok(Format::JsonFull),
+            other => Err(anyhow!(
+                "Unknown output format '{}', expected 'csv', 'parquet', 'geojson' or 'json-full'",
+                other
+            )),
+        }
+    }
+}
+
+/// A single resolved address, ready to be written out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputRecord {
+    pub address: String,
+    pub formatted_address: String,
+    pub township: String,
+    pub county: Option<String>,
+    pub state: Option<String>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    /// How the coordinates were placed, like `ROOFTOP` or `APPROXIMATE`, for providers that say.
+    pub location_type: Option<String>,
+    pub place_id: Option<String>,
+    pub status: String,
+    /// The provider that answered.
+    pub provider: String,
+    /// The types of the result itself, as opposed to the component the township came from.
+    pub result_types: Vec<String>,
+    /// How sure the provider is of the result, if it says.
+    pub confidence: Option<f64>,
+    /// The gazetteer name the township was matched to, if there's a gazetteer and a close enough name in it.
+    pub canonical_township: Option<String>,
+    /// The country, state, county and locality the result is in, from broadest to narrowest, leaving out any the
+    /// result doesn't have.
+    pub hierarchy: Vec<String>,
+}
+
+/// The component types that make up `OutputRecord::hierarchy`, from broadest to narrowest.
+const HIERARCHY_TYPES: [&str; 4] = [
+    "country",
+    "administrative_area_level_1",
+    "administrative_area_level_2",
+    "locality",
+];
+
+impl OutputRecord {
+    /// Builds a record from the input address and the result at `index` of its `GeoDataAddress`.
+    ///
+    /// The township is left empty, it's filled in once `get_township` has picked one.
+    pub fn from_geo_data(address: &str, geo_data: &GeoDataAddress, index: usize) -> OutputRecord {
+        let chosen = geo_data.results.get(index);
+        let component = |kind: &str| -> Option<&AddressComponent> {
+            chosen?
+                .address_components
+                .iter()
+                .find(|c| c.types.iter().any(|t| t == kind))
+        };
+        let geometry = chosen.and_then(|r| r.geometry.as_ref());
+        let location = geometry.map(|g| &g.location);
+
+        OutputRecord {
+            address: address.to_string(),
+            formatted_address: chosen.map(|r| r.formatted_address.clone()).unwrap_or_default(),
+            township: String::new(),
+            county: component("administrative_area_level_2").map(|c| c.long_name.clone()),
+            state: component("administrative_area_level_1").map(|c| c.short_name.clone()),
+            lat: location.map(|l| l.lat),
+            lng: location.map(|l| l.lng),
+            location_type: geometry
+                .and_then(|g| g.extra.get("location_type"))
+                .and_then(|t| t.as_str())
+                .map(str::to_string),
+            place_id: chosen.and_then(|r| r.place_id.clone()),
+            status: geo_data.status.clone(),
+            provider: String::new(),
+            result_types: chosen.map(|r| r.types.clone()).unwrap_or_default(),
+            confidence: chosen.and_then(|r| r.confidence),
+            canonical_township: None,
+            hierarchy: HIERARCHY_TYPES
+                .iter()
+                .filter_map(|kind| component(kind).map(|c| c.long_name.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// The properties of each GeoJSON feature, before any `FieldRename`.
+const GEOJSON_PROPERTIES: [&str; 9] = [
+    "address",
+    "formatted_address",
+    "township",
+    "county",
+    "state",
+    "place_id",
+    "status",
+    "provider",
+    "confidence",
+];
+
+/// Gives a field of the JSON output another name, given as `formatted_address=full_address`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldRename {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for FieldRename {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s
+            .split_once('=')
+            .map(|(from, to)| (from.trim(), to.trim()))
+            .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Expected a field rename like 'formatted_address=full_address', got '{}'",
+                    s
+                )
+            })?;
+        if !GEOJSON_PROPERTIES.contains(&from) {
+            return Err(anyhow!(
+                "Unknown field '{}', expected one of {}",
+                from,
+                GEOJSON_PROPERTIES.join(", ")
+            ));
+        }
+
+        Ok(FieldRename {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+}
+
+/// Which optional columns to include in tabular output, and what to call the fields of JSON output.
+#[derive(Debug, Clone, Default)]
+pub struct Columns {
+    /// The address exactly as it appeared in the input, written first.
+    pub input_address: bool,
+    /// The provider's formatted address, under the "Address" header.
+    pub formatted_address: bool,
+    /// The provider that answered, worth having once there's more than one to choose from.
+    pub provider: bool,
+    /// The result's types joined with semicolons, telling precise address matches apart from regional ones.
+    pub result_types: bool,
+    /// "Lat" and "Lng" columns, written with this many decimal places.
+    pub coordinates: Option<usize>,
+    /// A "WKT" column with the location as a `POINT(lng lat)`, written with this many decimal places.
+    pub wkt: Option<usize>,
+    /// A "Coord Precision" column flagging region-level centroids as `approximate` and everything else as
+    /// `precise`, blank when the provider doesn't say.
+    pub coord_precision: bool,
+    /// The provider's confidence in the result, blank when it doesn't report one.
+    pub confidence: bool,
+    /// The township's closest match in the gazetteer, blank when nothing was close enough.
+    pub canonical_township: bool,
+    /// A "Hierarchy" column with `OutputRecord::hierarchy` joined by this separator.
+    pub hierarchy: Option<String>,
+    /// New names for the properties of GeoJSON features.
+    pub renames: Vec<FieldRename>,
+}
+
+/// Something that resolved records can be written to.
+pub trait RecordWriter {
+    /// Writes `record`, which was built from `response`.
+    fn write(&mut self, record: &OutputRecord, response: &GeoDataAddress) -> Result<(), Error>;
+
+    /// Writes the whole `response` for `input`, before any township is picked from it, so even responses that don't
+    /// end up as a record are kept. Only formats that keep the raw responses need this.
+    fn write_response(&mut self, _input: &str, _response: &GeoDataAddress) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Flushes anything still buffered. Must be called once all records have been written.
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+/// Opens a writer for the given format at `path`, which may also be `-` for stdout or an `http(s)://` or `s3://`
+/// URL to upload to with `client`.
+pub fn open(
+    format: Format,
+    path: &Path,
+    columns: &Columns,
+    client: &reqwest::Client,
+) -> Result<Box<dyn RecordWriter>, Error> {
+    let mut sink = sink::open(&Destination::from_path(path)?, client)?;
+    if format.is_text() && path.extension().is_some_and(|ext| ext == "gz") {
+        sink = sink::gzip(sink);
+    }
+    match format {
+        Format::Csv => Ok(Box::new(CsvRecordWriter::create(sink, columns.clone())?)),
+        Format::Parquet => Ok(Box::new(ParquetRecordWriter::create(sink)?)),
+        Format::GeoJson => Ok(Box::new(GeoJsonRecordWriter::create(sink, &columns.renames)?)),
+        Format::JsonFull => Ok(Box::new(JsonFullRecordWriter::create(sink)?)),
+    }
+}
+
+/// Opens a writer for every `(format, path)` pair, writing each record to all of them.
+pub fn open_all(
+    outputs: &[(Format, PathBuf)],
+    columns: &Columns,
+    client: &reqwest::Client,
+) -> Result<Box<dyn RecordWriter>, Error> {
+    let writers = outputs
+        .iter()
+        .map(|(format, path)| open(*format, path, columns, client))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Box::new(MultiRecordWriter { writers }))
+}
+
+struct MultiRecordWriter {
+    writers: Vec<Box<dyn RecordWriter>>,
+}
+
+impl RecordWriter for MultiRecordWriter {
+    fn write(&mut self, record: &OutputRecord, response: &GeoDataAddress) -> Result<(), Error> {
+        for writer in &mut self.writers {
+            writer.write(record, response)?;
+        }
+        Ok(())
+    }
+
+    fn write_response(&mut self, input: &str, response: &GeoDataAddress) -> Result<(), Error> {
+        for writer in &mut self.writers {
+            writer.write_response(input, response)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Error> {
+        for writer in self.writers {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+struct CsvRecordWriter {
+    writer: Writer<Box<dyn Sink>>,
+    columns: Columns,
+    /// How many columns the header has, which every row must match.
+    header_len: usize,
+}
+
+impl CsvRecordWriter {
+    fn create(sink: Box<dyn Sink>, columns: Columns) -> Result<CsvRecordWriter, Error> {
+        let mut writer = Writer::from_writer(sink);
+        let mut header = vec![];
+        if columns.input_address {
+            header.push("Input Address");
+        }
+        if columns.formatted_address {
+            header.push("Address");
+        }
+        header.push("Township");
+        if columns.provider {
+            header.push("Provider");
+        }
+        if columns.result_types {
+            header.push("Result Types");
+        }
+        if columns.coordinates.is_some() {
+            header.extend(["Lat", "Lng"]);
+        }
+        if columns.wkt.is_some() {
+            header.push("WKT");
+        }
+        if columns.coord_precision {
+            header.push("Coord Precision");
+        }
+        if columns.confidence {
+            header.push("Confidence");
+        }
+        if columns.canonical_township {
+            header.push("Canonical Township");
+        }
+        if columns.hierarchy.is_some() {
+            header.push("Hierarchy");
+        }
+        writer.write_record(&header)?;
+
+        Ok(CsvRecordWriter {
+            writer,
+            columns,
+            header_len: header.len(),
+        })
+    }
+}
+
+impl RecordWriter for CsvRecordWriter {
+    fn write(&mut self, record: &OutputRecord, _: &GeoDataAddress) -> Result<(), Error> {
+        let result_types = record.result_types.join(";");
+        let coordinate = |value: Option<f64>| match (value, self.columns.coordinates) {
+            (Some(value), Some(precision)) => format_coordinate(value, precision),
+            _ => String::new(),
+        };
+        let (lat, lng) = (coordinate(record.lat), coordinate(record.lng));
+        let wkt = match (record.lat.zip(record.lng), self.columns.wkt) {
+            (Some((lat, lng)), Some(precision)) => format_wkt(lat, lng, precision),
+            _ => String::new(),
+        };
+        let confidence = record.confidence.map(|c| c.to_string()).unwrap_or_default();
+        let hierarchy = match &self.columns.hierarchy {
+            Some(separator) => record.hierarchy.join(separator),
+            None => String::new(),
+        };
+        let mut row = vec![];
+        if self.columns.input_address {
+            row.push(record.address.as_str());
+        }
+        if self.columns.formatted_address {
+            row.push(&record.formatted_address);
+        }
+        row.push(&record.township);
+        if self.columns.provider {
+            row.push(&record.provider);
+        }
+        if self.columns.result_types {
+            row.push(&result_types);
+        }
+        if self.columns.coordinates.is_some() {
+            row.extend([lat.as_str(), &lng]);
+        }
+        if self.columns.wkt.is_some() {
+            row.push(&wkt);
+        }
+        if self.columns.coord_precision {
+            row.push(coord_precision(record));
+        }
+        if self.columns.confidence {
+            row.push(&confidence);
+        }
+        if self.columns.canonical_township {
+            row.push(record.canonical_township.as_deref().unwrap_or_default());
+        }
+        if self.columns.hierarchy.is_some() {
+            row.push(&hierarchy);
+        }
+        // The header and rows are built from `Columns` separately, so a column added to one but not the other
+        // would otherwise fail with the csv crate's own, much vaguer error.
+        if row.len() != self.header_len {
+            bail!(
+                "Internal error: the CSV row for {} has {} columns but the header has {}",
+                record.address,
+                row.len(),
+                self.header_len
+            );
+        }
+        self.writer.write_record(row)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Error> {
+        self.writer
+            .into_inner()
+            .map_err(|e| anyhow!("Couldn't flush the CSV output: {}", e.error()))?
+            .finish()
+    }
+}
+
+/// Formats a coordinate with a fixed number of decimal places. Rust's formatting ignores the locale, so the
+/// separator is always a period.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert_eq!(format_coordinate(39.9361234, 4), "39.9361");
+/// assert_eq!(format_coordinate(-84.0, 2), "-84.00");
+/// ```
+fn format_coordinate(value: f64, precision: usize) -> String {
+    format!("{:.*}", precision, value)
+}
+
+/// Whether `record`'s coordinates are the centroid of a region rather than a point on the address, going by its
+/// `location_type`. Google marks region-level matches `APPROXIMATE`.
+fn coord_precision(record: &OutputRecord) -> &'static str {
+    match record.location_type.as_deref() {
+        Some("APPROXIMATE") => "approximate",
+        Some(_) if record.lat.is_some() => "precise",
+        _ => "",
+    }
+}
+
+/// Formats a location as a Well-Known Text point, which puts the longitude first.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert_eq!(format_wkt(39.9361234, -84.0253211, 4), "POINT(-84.0253 39.9361)");
+/// ```
+fn format_wkt(lat: f64, lng: f64, precision: usize) -> String {
+    format!(
+        "POINT({} {})",
+        format_coordinate(lng, precision),
+        format_coordinate(lat, precision)
+    )
+}
+
+/// Writes records as Snappy-compressed Parquet, buffering rows and flushing them in batches of `PARQUET_BATCH_SIZE`.
+struct ParquetRecordWriter {
+    writer: ArrowWriter<Box<dyn Sink>>,
+    schema: Arc<Schema>,
+    pending: Vec<OutputRecord>,
+}
+
+impl ParquetRecordWriter {
+    fn create(sink: Box<dyn Sink>) -> Result<ParquetRecordWriter, Error> {
+        let schema = Arc::new(parquet_schema());
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer = ArrowWriter::try_new(sink, schema.clone(), Some(properties))?;
+
+        Ok(ParquetRecordWriter {
+            writer,
+            schema,
+            pending: Vec::with_capacity(PARQUET_BATCH_SIZE),
+        })
+    }
+
+    fn flush_pending(&mut self) -> Result<(), Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let rows = &self.pending;
+        let strings = |f: fn(&OutputRecord) -> Option<&str>| -> ArrayRef {
+            Arc::new(rows.iter().map(f).collect::<StringArray>())
+        };
+        let floats = |f: fn(&OutputRecord) -> Option<f64>| -> ArrayRef {
+            Arc::new(rows.iter().map(f).collect::<Float64Array>())
+        };
+
+        let columns = vec![
+            strings(|r| Some(&r.address)),
+            strings(|r| Some(&r.formatted_address)),
+            strings(|r| Some(&r.township)),
+            strings(|r| r.county.as_deref()),
+            strings(|r| r.state.as_deref()),
+            floats(|r| r.lat),
+            floats(|r| r.lng),
+            strings(|r| r.place_id.as_deref()),
+            strings(|r| Some(&r.status)),
+            strings(|r| Some(&r.provider)),
+            floats(|r| r.confidence),
+        ];
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.pending.clear();
+
+        Ok(())
+    }
+}
+
+impl RecordWriter for ParquetRecordWriter {
+    fn write(&mut self, record: &OutputRecord, _: &GeoDataAddress) -> Result<(), Error> {
+        self.pending.push(record.clone());
+
+        if self.pending.len() >= PARQUET_BATCH_SIZE {
+            self.flush_pending()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.flush_pending()?;
+        self.writer.into_inner()?.finish()
+    }
+}
+
+/// Writes a GeoJSON FeatureCollection, streaming features out one at a time. Records without coordinates get a
+/// `null` geometry, which GeoJSON allows. Records with coordinates off the globe are rejected rather than written as
+/// a point no GeoJSON reader would accept.
+struct GeoJsonRecordWriter {
+    writer: Box<dyn Sink>,
+    written: usize,
+    /// The name each of `GEOJSON_PROPERTIES` is written under, in the same order.
+    names: Vec<String>,
+}
+
+impl GeoJsonRecordWriter {
+    fn create(mut writer: Box<dyn Sink>, renames: &[FieldRename]) -> Result<GeoJsonRecordWriter, Error> {
+        let names: Vec<String> = GEOJSON_PROPERTIES
+            .iter()
+            .map(|&property| match renames.iter().rev().find(|r| r.from == property) {
+                Some(rename) => rename.to.clone(),
+                None => property.to_string(),
+            })
+            .collect();
+        if let Some(name) = names
+            .iter()
+            .enumerate()
+            .find_map(|(i, n)| names[..i].contains(n).then_some(n))
+        {
+            bail!("Two GeoJSON properties would both be named '{}'", name);
+        }
+        write!(writer, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+
+        Ok(GeoJsonRecordWriter {
+            writer,
+            written: 0,
+            names,
+        })
+    }
+}
+
+impl RecordWriter for GeoJsonRecordWriter {
+    fn write(&mut self, record: &OutputRecord, _: &GeoDataAddress) -> Result<(), Error> {
+        let geometry = match (record.lat, record.lng) {
+            (Some(lat), Some(lng)) if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) => {
+                bail!("{}, {} isn't a valid location for {}", lat, lng, record.address)
+            }
+            (Some(lat), Some(lng)) => serde_json::json!({ "type": "Point", "coordinates": [lng, lat] }),
+            _ => serde_json::Value::Null,
+        };
+        // In the order of `GEOJSON_PROPERTIES`.
+        let values = [
+            serde_json::json!(record.address),
+            serde_json::json!(record.formatted_address),
+            serde_json::json!(record.township),
+            serde_json::json!(record.county),
+            serde_json::json!(record.state),
+            serde_json::json!(record.place_id),
+            serde_json::json!(record.status),
+            serde_json::json!(record.provider),
+            serde_json::json!(record.confidence),
+        ];
+        let properties: serde_json::Map<String, serde_json::Value> = self.names.iter().cloned().zip(values).collect();
+        let feature = serde_json::json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": properties,
+        });
+
+        let separator = if self.written == 0 { "" } else { "," };
+        write!(self.writer, "{}\n{}", separator, feature)?;
+        self.written += 1;
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        writeln!(self.writer, "\n]}}")?;
+        self.writer.finish()
+    }
+}
+
+/// Writes a single pretty-printed JSON object mapping each input address to its full response, whether or not a
+/// township came of it.
+///
+/// Entries are streamed out as they arrive rather than collected into one big value, so memory use doesn't grow
+/// with the size of the run. Repeated input addresses are only written once, keeping the keys unique.
+struct JsonFullRecordWriter {
+    writer: Box<dyn Sink>,
+    written: HashSet<String>,
+}
+
+impl JsonFullRecordWriter {
+    fn create(mut writer: Box<dyn Sink>) -> Result<JsonFullRecordWriter, Error> {
+        write!(writer, "{{")?;
+
+        Ok(JsonFullRecordWriter {
+            writer,
+            written: HashSet::new(),
+        })
+    }
+}
+
+impl RecordWriter for JsonFullRecordWriter {
+    fn write(&mut self, _: &OutputRecord, _: &GeoDataAddress) -> Result<(), Error> {
+        // Already written by `write_response`.
+        Ok(())
+    }
+
+    fn write_response(&mut self, input: &str, response: &GeoDataAddress) -> Result<(), Error> {
+        if self.written.contains(input) {
+            return Ok(());
+        }
+
+        let separator = if self.written.is_empty() { "" } else { "," };
+        let value = serde_json::to_string_pretty(response)?.replace('\n', "\n  ");
+        write!(
+            self.writer,
+            "{}\n  {}: {}",
+            separator,
+            serde_json::to_string(input)?,
+            value
+        )?;
+        self.written.insert(input.to_string());
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        writeln!(self.writer, "\n}}")?;
+        self.writer.finish()
+    }
+}
+
+/// The columns of the Parquet output, in order.
+fn parquet_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("address", DataType::Utf8, false),
+        Field::new("formatted_address", DataType::Utf8, false),
+        Field::new("township", DataType::Utf8, false),
+        Field::new("county", DataType::Utf8, true),
+        Field::new("state", DataType::Utf8, true),
+        Field::new("lat", DataType::Float64, true),
+        Field::new("lng", DataType::Float64, true),
+        Field::new("place_id", DataType::Utf8, true),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("provider", DataType::Utf8, false),
+        Field::new("confidence", DataType::Float64, true),
+    ])
+}
+
+/// A CSV report of the addresses that couldn't be resolved, and why.
+///
+/// Optionally also writes a plain list of just the failed input addresses, one per line, which can be fed straight
+/// back in as input.
+pub struct FailureReport {
+    path: PathBuf,
+    writer: Writer<File>,
+    plain: Option<BufWriter<File>>,
+    count: usize,
+}
+
+impl FailureReport {
+    pub fn create(path: &Path, plain_path: Option<&Path>) -> Result<FailureReport, Error> {
+        let mut writer = Writer::from_path(path)?;
+        writer.write_record(["Address", "Reason"])?;
+        let plain = match plain_path {
+            Some(plain_path) => Some(BufWriter::new(File::create(plain_path)?)),
+            None => None,
+        };
+
+        Ok(FailureReport {
+            path: path.to_path_buf(),
+            writer,
+            plain,
+            count: 0,
+        })
+    }
+
+    pub fn record(&mut self, address: &str, reason: &str) -> Result<(), Error> {
+        self.writer.write_record([address, reason])?;
+        if let Some(plain) = &mut self.plain {
+            writeln!(plain, "{}", address)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flushes the report, printing where to find it if anything failed.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        if let Some(plain) = &mut self.plain {
+            plain.flush()?;
+        }
+
+        if self.count > 0 {
+            log!("{} addresses failed, see {}", self.count, self.path.display());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ok, result};
+    use std::fs;
+
+    /// Writes `records` as CSV with `columns`, returning the lines written.
+    fn write_csv(columns: Columns, records: &[OutputRecord]) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.csv");
+        let mut writer = open(Format::Csv, &path, &columns, &reqwest::Client::new()).unwrap();
+        for record in records {
+            writer.write(record, &ok(vec![])).unwrap();
+        }
+        writer.finish().unwrap();
+
+        fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn result_types_are_joined_with_semicolons() {
+        let mut value = result(&[("Bethel", &["locality", "political"])]);
+        value["types"] = serde_json::json!(["street_address", "premise"]);
+        let record = OutputRecord::from_geo_data("1 Main St", &ok(vec![value]), 0);
+        assert_eq!(record.result_types, ["street_address", "premise"]);
+
+        let columns = Columns {
+            result_types: true,
+            ..Columns::default()
+        };
+        assert_eq!(
+            write_csv(columns, &[record]),
+            ["Township,Result Types", ",street_address;premise"]
+        );
+    }
+
+    /// A record for an address in Bethel at `lat`, `lng`.
+    fn located(lat: f64, lng: f64) -> OutputRecord {
+        let mut value = result(&[("Bethel", &["locality", "political"])]);
+        value["geometry"] = serde_json::json!({ "location": { "lat": lat, "lng": lng } });
+        OutputRecord::from_geo_data("1 Main St", &ok(vec![value]), 0)
+    }
+
+    #[test]
+    fn coordinates_have_the_requested_precision() {
+        assert_eq!(format_coordinate(39.9361234, 4), "39.9361");
+        assert_eq!(format_coordinate(-84.0, 2), "-84.00");
+        assert_eq!(format_coordinate(0.5, 0), "0");
+
+        let columns = Columns {
+            coordinates: Some(3),
+            ..Columns::default()
+        };
+        assert_eq!(
+            write_csv(columns, &[located(39.9361234, -84.0253211)]),
+            ["Township,Lat,Lng", ",39.936,-84.025"]
+        );
+    }
+
+    #[test]
+    fn wkt_puts_the_longitude_first() {
+        assert_eq!(format_wkt(39.9361234, -84.0253211, 4), "POINT(-84.0253 39.9361)");
+        assert_eq!(format_wkt(39.9, -84.0, 2), "POINT(-84.00 39.90)");
+
+        let columns = Columns {
+            wkt: Some(5),
+            ..Columns::default()
+        };
+        let mut unlocated = OutputRecord::from_geo_data("2 Oak Ave", &ok(vec![]), 0);
+        unlocated.township = String::from("Enon");
+        assert_eq!(
+            write_csv(columns, &[located(39.9361234, -84.0253211), unlocated]),
+            ["Township,WKT", ",POINT(-84.02532 39.93612)", "Enon,"]
+        );
+    }
+
+    #[test]
+    fn hierarchy_runs_from_country_to_locality() {
+        let full = result(&[
+            ("Springfield", &["locality", "political"]),
+            ("Clark County", &["administrative_area_level_2", "political"]),
+            ("Ohio", &["administrative_area_level_1", "political"]),
+            ("United States", &["country", "political"]),
+        ]);
+        let record = OutputRecord::from_geo_data("1 Main St", &ok(vec![full]), 0);
+        assert_eq!(
+            record.hierarchy,
+            ["United States", "Ohio", "Clark County", "Springfield"]
+        );
+
+        let without_county = result(&[
+            ("Springfield", &["locality", "political"]),
+            ("Ohio", &["administrative_area_level_1", "political"]),
+            ("United States", &["country", "political"]),
+        ]);
+        let mut records = vec![OutputRecord::from_geo_data("2 Oak Ave", &ok(vec![without_county]), 0)];
+        assert_eq!(records[0].hierarchy, ["United States", "Ohio", "Springfield"]);
+        records.push(OutputRecord::from_geo_data("3 Elm Rd", &ok(vec![]), 0));
+
+        let columns = Columns {
+            hierarchy: Some(String::from(" > ")),
+            ..Columns::default()
+        };
+        assert_eq!(
+            write_csv(columns, &records),
+            ["Township,Hierarchy", ",United States > Ohio > Springfield", ","]
+        );
+    }
+
+    #[test]
+    fn misaligned_row_names_the_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = sink::open(
+            &Destination::File(dir.path().join("output.csv")),
+            &reqwest::Client::new(),
+        )
+        .unwrap();
+        let columns = Columns {
+            provider: true,
+            ..Columns::default()
+        };
+        let mut writer = CsvRecordWriter::create(sink, columns).unwrap();
+        // As if a column were added to the header but not to the rows.
+        writer.header_len += 1;
+
+        let record = OutputRecord::from_geo_data("1 Main St", &ok(vec![]), 0);
+        let error = writer.write(&record, &ok(vec![])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Internal error: the CSV row for 1 Main St has 2 columns but the header has 3"
+        );
+    }
+}
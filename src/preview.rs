@@ -0,0 +1,122 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// Picks `size` items at random, spread across the whole list rather than taken from the top of it.
+///
+/// The same `seed` always produces the same sample. Without one, the sample is different every run.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let addresses: Vec<String> = (0..100).map(|i| format!("{} Main St", i)).collect();
+/// assert_eq!(sample(&addresses, 5, Some(42)), sample(&addresses, 5, Some(42)));
+/// ```
+pub fn sample<T: Clone>(items: &[T], size: usize, seed: Option<u64>) -> Vec<T> {
+    items.choose_multiple(&mut rng(seed), size).cloned().collect()
+}
+
+/// Puts `items` in a random order, the same one every run for the same `seed`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let (mut a, mut b): (Vec<usize>, Vec<usize>) = ((0..10).collect(), (0..10).collect());
+/// shuffle(&mut a, Some(7));
+/// shuffle(&mut b, Some(7));
+/// assert_eq!(a, b);
+/// ```
+pub fn shuffle<T>(items: &mut [T], seed: Option<u64>) {
+    items.shuffle(&mut rng(seed));
+}
+
+fn rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Prints `rows` as a table with left-aligned columns sized to fit their widest cell.
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        log!("{}", padded.join(" | ").trim_end());
+    };
+
+    print_row(headers.to_vec());
+    log!(
+        "{}",
+        widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-")
+    );
+    for row in rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses() -> Vec<String> {
+        (0..100).map(|i| format!("{} Main St", i)).collect()
+    }
+
+    #[test]
+    fn sample_has_the_requested_size() {
+        let addresses = addresses();
+        let sampled = sample(&addresses, 5, Some(42));
+
+        assert_eq!(sampled.len(), 5);
+        assert!(sampled.iter().all(|a| addresses.contains(a)));
+        let mut distinct = sampled.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 5);
+    }
+
+    #[test]
+    fn sample_is_the_same_for_the_same_seed() {
+        let addresses = addresses();
+
+        assert_eq!(sample(&addresses, 5, Some(42)), sample(&addresses, 5, Some(42)));
+        assert_ne!(sample(&addresses, 5, Some(42)), sample(&addresses, 5, Some(43)));
+    }
+
+    #[test]
+    fn sample_larger_than_the_input_takes_everything() {
+        let addresses = addresses();
+        assert_eq!(sample(&addresses, 500, Some(42)).len(), 100);
+    }
+
+    #[test]
+    fn shuffle_is_the_same_for_the_same_seed() {
+        let shuffled = |seed| {
+            let mut addresses = addresses();
+            shuffle(&mut addresses, Some(seed));
+            addresses
+        };
+
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+        assert_ne!(shuffled(7), addresses());
+        let mut sorted = shuffled(7);
+        sorted.sort_by_key(|a| a.split(' ').next().unwrap().parse::<usize>().unwrap());
+        assert_eq!(sorted, addresses());
+    }
+}
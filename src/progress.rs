@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+/// How much weight the latest address gets in the smoothed time per address. Lower values give a steadier ETA that
+/// is slower to react when the pace really changes.
+pub const SMOOTHING: f64 = 0.2;
+
+/// Estimates the time left in a run from an exponentially smoothed average of how long each address took, so one
+/// slow request or a burst of retries doesn't throw the estimate around.
+#[derive(Debug, Default)]
+pub struct Eta {
+    /// The smoothed seconds per address, `None` until the first one finishes.
+    average: Option<f64>,
+}
+
+impl Eta {
+    /// Folds in how long the latest address took.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// let mut eta = Eta::default();
+    /// for seconds in [1, 1, 6] {
+    ///     eta.record(Duration::from_secs(seconds));
+    /// }
+    /// // 1, then 0.2 * 1 + 0.8 * 1 = 1, then 0.2 * 6 + 0.8 * 1 = 2
+    /// assert_eq!(eta.remaining(10).map(|left| left.as_secs()), Some(20));
+    /// ```
+    pub fn record(&mut self, elapsed: Duration) {
+        let sample = elapsed.as_secs_f64();
+        self.average = Some(match self.average {
+            Some(average) => SMOOTHING * sample + (1.0 - SMOOTHING) * average,
+            None => sample,
+        });
+    }
+
+    /// How long the `left` remaining addresses should take at the current pace.
+    pub fn remaining(&self, left: usize) -> Option<Duration> {
+        self.average
+            .map(|average| Duration::from_secs_f64(average * left as f64))
+    }
+}
+
+/// Formats a duration down to the second, like `1h 02m`, `3m 20s` or `12s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooths_the_time_per_address() {
+        let mut eta = Eta::default();
+        assert_eq!(eta.remaining(10), None);
+
+        eta.record(Duration::from_secs(2));
+        assert_eq!(eta.remaining(10), Some(Duration::from_secs(20)));
+
+        // 0.2 * 12 + 0.8 * 2 = 4, so one slow address moves the estimate a fifth of the way.
+        eta.record(Duration::from_secs(12));
+        assert_eq!(eta.remaining(10).map(|left| left.as_secs_f64().round()), Some(40.0));
+
+        // 0.2 * 4 + 0.8 * 4 = 4, the same pace leaves it alone.
+        eta.record(Duration::from_secs(4));
+        assert_eq!(eta.remaining(5).map(|left| left.as_secs_f64().round()), Some(20.0));
+        assert_eq!(eta.remaining(0), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::from_secs(12)), "12s");
+        assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 02m");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::geocoder::{ResponseTooLarge, UnreadableResponse};
+use crate::GeoDataAddress;
+use anyhow::Error;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Statuses Google returns for transient problems that are worth another attempt.
+pub const RETRYABLE_STATUSES: [&str; 2] = ["OVER_QUERY_LIMIT", "UNKNOWN_ERROR"];
+
+/// HTTP statuses worth another attempt: timeouts, rate limiting and server errors.
+pub const RETRYABLE_HTTP_CODES: [u16; 6] = [408, 429, 500, 502, 503, 504];
+
+/// Which failures from a provider are worth another attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Response statuses, like `OVER_QUERY_LIMIT`.
+    pub statuses: Vec<String>,
+    /// HTTP status codes. Requests that fail without any HTTP status, like dropped connections, are always retried,
+    /// unless the body came back unreadable or too large.
+    pub http_codes: Vec<u16>,
+}
+
+impl RetryPolicy {
+    pub fn is_retryable_status(&self, status: &str) -> bool {
+        self.statuses.iter().any(|s| s == status)
+    }
+
+    /// Whether a failed request is worth another attempt.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// let policy = RetryPolicy { statuses: vec![], http_codes: vec![503] };
+    /// assert!(!policy.is_retryable_error(&ResponseTooLarge { limit: 10 }.into()));
+    /// let html = UnreadableResponse { message: String::from("Received a non-JSON response") };
+    /// assert!(!policy.is_retryable_error(&html.into()));
+    /// assert!(policy.is_retryable_error(&anyhow!("Connection reset")));
+    /// ```
+    pub fn is_retryable_error(&self, error: &Error) -> bool {
+        if error.is::<ResponseTooLarge>() || error.is::<UnreadableResponse>() {
+            return false;
+        }
+
+        match error.downcast_ref::<reqwest::Error>().and_then(reqwest::Error::status) {
+            Some(status) => self.http_codes.contains(&status.as_u16()),
+            None => true,
+        }
+    }
+}
+
+/// Whether `response` says the daily quota is used up, in which case retrying straight away is pointless. Google
+/// sends `OVER_DAILY_LIMIT`, or `OVER_QUERY_LIMIT` with a message about the daily quota.
+pub fn is_quota_exhausted(response: &GeoDataAddress) -> bool {
+    let message = response
+        .extra
+        .get("error_message")
+        .and_then(|m| m.as_str())
+        .unwrap_or_default();
+    response.status == "OVER_DAILY_LIMIT"
+        || response.status == "OVER_QUERY_LIMIT" && message.to_lowercase().contains("daily")
+}
+
+/// Delay before the first retry, doubled for every retry after that.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// How long to wait before retry number `attempt` (starting at 1).
+pub fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY * 2u32.saturating_pow(attempt.saturating_sub(1))
+}
+
+/// A cap on the number of retries across the whole run, shared by every address.
+///
+/// Once the budget is spent, `try_acquire` always returns `false` and failures are no longer retried. A budget
+/// created with `None` never runs out.
+///
+/// The budget can also allow pausing for an exhausted daily quota to reset, see `with_quota_wait`.
+#[derive(Debug)]
+pub struct RetryBudget {
+    remaining: Option<AtomicUsize>,
+    quota_wait: Option<Duration>,
+    max_quota_wait: Duration,
+    /// Milliseconds spent waiting for the quota so far.
+    quota_waited: AtomicU64,
+}
+
+impl RetryBudget {
+    pub fn new(max_total_retries: Option<usize>) -> RetryBudget {
+        RetryBudget {
+            remaining: max_total_retries.map(AtomicUsize::new),
+            quota_wait: None,
+            max_quota_wait: Duration::ZERO,
+            quota_waited: AtomicU64::new(0),
+        }
+    }
+
+    /// Waits `wait` whenever the daily quota runs out, for up to `max` in total across the run.
+    pub fn with_quota_wait(mut self, wait: Duration, max: Duration) -> RetryBudget {
+        self.quota_wait = Some(wait);
+        self.max_quota_wait = max;
+        self
+    }
+
+    /// Takes one wait for the quota to reset from the budget, returning how long to wait, or `None` once the
+    /// total allowed wait would be exceeded.
+    pub fn try_wait_for_quota(&self) -> Option<Duration> {
+        let wait = self.quota_wait?;
+        let (wait_ms, max_ms) = (wait.as_millis() as u64, self.max_quota_wait.as_millis() as u64);
+        self.quota_waited
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |waited| {
+                Some(waited + wait_ms).filter(|total| *total <= max_ms)
+            })
+            .ok()
+            .map(|_| wait)
+    }
+
+    /// Takes one retry from the budget, returning `false` if there is none left.
+    pub fn try_acquire(&self) -> bool {
+        match &self.remaining {
+            None => true,
+            Some(remaining) => remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok(),
+        }
+    }
+}
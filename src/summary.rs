@@ -0,0 +1,201 @@
+use crate::{preview, GeoDataAddress};
+use anyhow::Error;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What the township falls back to being counted as when no configured component type matched.
+const FALLBACK_SOURCE: &str = "fallback";
+
+/// Tallies what happened to every input address, printed once the run is over.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub resolved: usize,
+    pub failed: usize,
+    /// How many resolved addresses got their township from each component type.
+    pub sources: BTreeMap<String, usize>,
+    /// How many resolved townships had no close enough match in the gazetteer.
+    pub unmatched_canonical: usize,
+    /// How many records were resolved but couldn't be written, with `--continue-on-write-error`.
+    pub skipped_writes: usize,
+    /// How many results were outside `--expect-state`.
+    pub out_of_region: usize,
+    /// How many components of each type were in all the results of every response, for `--type-stats`.
+    pub type_counts: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// Counts a resolved address whose township came from `source`.
+    pub fn resolved(&mut self, source: Option<&str>) {
+        self.resolved += 1;
+        *self
+            .sources
+            .entry(source.unwrap_or(FALLBACK_SOURCE).to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn failed(&mut self) {
+        self.failed += 1;
+    }
+
+    /// Tallies the type of every component in every result of `response`.
+    pub fn count_types(&mut self, response: &GeoDataAddress) {
+        let types = response
+            .results
+            .iter()
+            .flat_map(|r| &r.address_components)
+            .flat_map(|c| &c.types);
+        for kind in types {
+            *self.type_counts.entry(kind.clone()).or_insert(0) += 1;
+        }
+    }
+
+    /// The township types that never supplied a township, most likely typos.
+    pub fn unmatched_types<'a>(&self, types: &[&'a str]) -> Vec<&'a str> {
+        types
+            .iter()
+            .filter(|t| !self.sources.contains_key(**t))
+            .copied()
+            .collect()
+    }
+
+    /// Prints the tallies, warning about any of the township `types` given on the command line that never supplied
+    /// a township.
+    pub fn print(&self, types: &[&str]) {
+        log!("Resolved {} addresses, {} failed.", self.resolved, self.failed);
+
+        if self.skipped_writes > 0 {
+            log!("{} records couldn't be written and were skipped.", self.skipped_writes);
+        }
+
+        if self.out_of_region > 0 {
+            log!("{} results were outside the expected state.", self.out_of_region);
+        }
+
+        if self.unmatched_canonical > 0 {
+            log!("{} townships had no match in the gazetteer.", self.unmatched_canonical);
+        }
+
+        if !self.sources.is_empty() {
+            log!("Townships by source:");
+            for (source, count) in &self.sources {
+                log!("  {}: {}", source, count);
+            }
+        }
+
+        if self.resolved > 0 {
+            for kind in self.unmatched_types(types) {
+                log!(
+                    "Warning: township type '{}' never supplied a township, check --township-types and --country-township-types for typos",
+                    kind
+                );
+            }
+        }
+    }
+}
+
+/// Prints how often each component type appeared, most common first, or writes it as a CSV to `path`.
+pub fn write_type_stats(summaries: &[Summary], path: Option<&Path>) -> Result<(), Error> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (kind, count) in summaries.iter().flat_map(|s| &s.type_counts) {
+        *counts.entry(kind).or_insert(0) += count;
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    match path {
+        Some(path) => {
+            let mut writer = csv::Writer::from_path(path)?;
+            writer.write_record(["Type", "Count"])?;
+            for (kind, count) in &counts {
+                writer.write_record([kind, &count.to_string().as_str()])?;
+            }
+            writer.flush()?;
+            log!("Wrote component type counts to {}", path.display());
+        }
+        None => {
+            let rows: Vec<Vec<String>> = counts
+                .iter()
+                .map(|(kind, count)| vec![kind.to_string(), count.to_string()])
+                .collect();
+            preview::print_table(&["Type", "Count"], &rows);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ok, result};
+
+    #[test]
+    fn tallies_townships_by_source() {
+        let mut summary = Summary::default();
+        for source in [
+            Some("locality"),
+            Some("administrative_area_level_3"),
+            Some("locality"),
+            None,
+            Some("administrative_area_level_2"),
+        ] {
+            summary.resolved(source);
+        }
+        summary.failed();
+
+        assert_eq!(summary.resolved, 5);
+        assert_eq!(summary.failed, 1);
+        let sources: Vec<(&str, usize)> = summary.sources.iter().map(|(s, n)| (s.as_str(), *n)).collect();
+        assert_eq!(
+            sources,
+            [
+                ("administrative_area_level_2", 1),
+                ("administrative_area_level_3", 1),
+                ("fallback", 1),
+                ("locality", 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_types_are_the_ones_that_never_supplied_a_township() {
+        let mut summary = Summary::default();
+        summary.resolved(Some("locality"));
+        summary.resolved(None);
+
+        // `political` is on nearly every component, but never the one a township came from.
+        assert_eq!(
+            summary.unmatched_types(&["locality", "localty", "political"]),
+            ["localty", "political"]
+        );
+    }
+
+    #[test]
+    fn type_stats_tally_every_component_of_every_result() {
+        let (mut first, mut second) = (Summary::default(), Summary::default());
+        first.count_types(&ok(vec![
+            result(&[
+                ("Bethel", &["locality", "political"]),
+                ("Clark County", &["administrative_area_level_2", "political"]),
+            ]),
+            result(&[("Enon", &["locality", "political"])]),
+        ]));
+        second.count_types(&ok(vec![result(&[
+            ("Northridge", &["neighborhood", "political"]),
+            ("Clark County", &["administrative_area_level_2", "political"]),
+        ])]));
+        second.count_types(&ok(vec![]));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("types.csv");
+        write_type_stats(&[first, second], Some(&path)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "Type,Count\n\
+             political,5\n\
+             administrative_area_level_2,2\n\
+             locality,2\n\
+             neighborhood,1\n"
+        );
+    }
+}
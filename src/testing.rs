@@ -0,0 +1,25 @@
+//! Builders for the responses unit tests run against.
+
+use crate::GeoDataAddress;
+use serde_json::{json, Value};
+
+/// A response from JSON in Google's format.
+pub fn response(value: Value) -> GeoDataAddress {
+    serde_json::from_value(value).expect("test responses are in Google's format")
+}
+
+/// A result made of `components`, each a name and its types, with the names joined up as its formatted address.
+pub fn result(components: &[(&str, &[&str])]) -> Value {
+    let names: Vec<&str> = components.iter().map(|(name, _)| *name).collect();
+    let components: Vec<Value> = components
+        .iter()
+        .map(|(name, types)| json!({ "long_name": name, "short_name": name, "types": types }))
+        .collect();
+
+    json!({ "formatted_address": names.join(", "), "address_components": components })
+}
+
+/// An `OK` response with one result in each of `results`.
+pub fn ok(results: Vec<Value>) -> GeoDataAddress {
+    response(json!({ "status": "OK", "results": results }))
+}
@@ -0,0 +1,633 @@
+use crate::{AddressResult, GeoDataAddress};
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Address component types that count as a township, from most to least specific.
+pub const DEFAULT_TOWNSHIP_TYPES: [&str; 3] =
+    ["locality", "administrative_area_level_3", "administrative_area_level_2"];
+
+/// Township types for the results from one country, given as `DE=administrative_area_level_3,locality`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CountryTypes {
+    /// The short name of the result's `country` component, an ISO code like `DE`.
+    pub country: String,
+    pub types: Vec<String>,
+}
+
+impl FromStr for CountryTypes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (country, types) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected country township types like 'DE=locality', got '{}'", s))?;
+        let types: Vec<String> = types
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+        if country.trim().is_empty() || types.is_empty() {
+            return Err(anyhow!(
+                "Expected country township types like 'DE=locality', got '{}'",
+                s
+            ));
+        }
+
+        Ok(CountryTypes {
+            country: country.trim().to_uppercase(),
+            types,
+        })
+    }
+}
+
+/// How the township types' order is used to pick one of a result's components.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    /// The component with the most specific type wins, wherever it is in the result.
+    #[default]
+    Strict,
+    /// The first component with any of the types wins, in the provider's order, so a less specific type listed
+    /// earlier beats a more specific one listed later.
+    FirstMatch,
+}
+
+impl FromStr for MatchMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(MatchMode::Strict),
+            "first-match" => Ok(MatchMode::FirstMatch),
+            other => Err(anyhow!(
+                "Unknown match mode '{}', expected 'strict' or 'first-match'",
+                other
+            )),
+        }
+    }
+}
+
+/// Which component types count as a township, from most to least specific, for each country.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Precedence<'a> {
+    /// The types for results from any country without its own.
+    pub default: Vec<&'a str>,
+    /// The types for results from particular countries, keyed by their upper-cased short name.
+    pub countries: HashMap<String, Vec<&'a str>>,
+    pub mode: MatchMode,
+}
+
+impl<'a> Precedence<'a> {
+    /// The same `types` for every country.
+    pub fn new(types: Vec<&'a str>) -> Precedence<'a> {
+        Precedence {
+            default: types,
+            countries: HashMap::new(),
+            mode: MatchMode::default(),
+        }
+    }
+
+    /// The types to pick `result`'s township with, going by its `country` component.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// let mut precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+    /// precedence.countries.insert(String::from("DE"), vec!["administrative_area_level_3"]);
+    /// assert_eq!(precedence.types_for(&german_result), ["administrative_area_level_3"]);
+    /// assert_eq!(precedence.types_for(&american_result), DEFAULT_TOWNSHIP_TYPES);
+    /// ```
+    pub fn types_for(&self, result: &AddressResult) -> &[&'a str] {
+        country_of(result)
+            .and_then(|country| self.countries.get(&country.to_uppercase()))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// The short name of `result`'s `country` component.
+fn country_of(result: &AddressResult) -> Option<&str> {
+    result
+        .address_components
+        .iter()
+        .find(|c| c.types.iter().any(|t| t == "country"))
+        .map(|country| country.short_name.as_str())
+}
+
+/// The township picked for a `GeoDataAddress`, along with how it was picked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TownshipMatch {
+    /// Which of the response's results the township was taken from.
+    pub result_index: usize,
+    /// The formatted address of that same result, so it always describes the place the township belongs to.
+    pub formatted_address: String,
+    pub township: String,
+    /// The configured type of the component the township came from, or `None` if no component matched.
+    pub source: Option<String>,
+}
+
+/// Why no township could be picked for a `GeoDataAddress`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Miss {
+    /// The provider didn't answer with `OK`, e.g. `ZERO_RESULTS`.
+    Status(String),
+    /// The provider said `OK` but sent back no results, which Google can technically do.
+    EmptyResults,
+}
+
+impl fmt::Display for Miss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Miss::Status(status) => write!(f, "No township found (status {})", status),
+            Miss::EmptyResults => write!(f, "No township found (status OK, but empty results)"),
+        }
+    }
+}
+
+/// Finds the correct township in a `GeoDataAddress`.
+///
+/// This function uses the first result in a given `GeoDataAddress`. It picks the component with the most specific of
+/// the types `precedence` has for the result's country, so with the default `DEFAULT_TOWNSHIP_TYPES` a locality
+/// takes precedence over a level 3 area, which takes precedence over level 2. With `MatchMode::FirstMatch` it instead
+/// picks the first component with any of the types. Returns the township along with the formatted address and the
+/// component type it came from, or a `Miss` saying why the lookup failed.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let fake_addr = String::from("123 Fake Addr Rd, Springfield, OH");
+/// let google: Box<dyn Geocoder> = Box::new(Google::new(api_key, ProviderSettings::default()));
+/// let cache = Mutex::new(Cache::default());
+/// let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+/// let geo_data = get_geo_data(&client, &[fake_addr], &[google], &precedence, &cache, 3, 1, &RetryBudget::new(None));
+/// let township = get_township(&geo_data[0].response, &precedence)?.township; // "Springfield City"
+/// ```
+pub fn get_township(result: &GeoDataAddress, precedence: &Precedence) -> Result<TownshipMatch, Miss> {
+    check_status(result)?;
+    Ok(township_in(result, 0, precedence))
+}
+
+/// Like `get_township`, but picks a township from every result rather than just the first, in the order the
+/// provider ranked them.
+pub fn get_all_townships(result: &GeoDataAddress, precedence: &Precedence) -> Result<Vec<TownshipMatch>, Miss> {
+    check_status(result)?;
+    Ok((0..result.results.len())
+        .map(|i| township_in(result, i, precedence))
+        .collect())
+}
+
+fn check_status(result: &GeoDataAddress) -> Result<(), Miss> {
+    if result.status != "OK" {
+        return Err(Miss::Status(result.status.clone()));
+    }
+
+    if result.results.is_empty() {
+        return Err(Miss::EmptyResults);
+    }
+
+    Ok(())
+}
+
+/// Picks the township from the result at `index`, which must exist.
+fn township_in(result: &GeoDataAddress, index: usize, precedence: &Precedence) -> TownshipMatch {
+    let explanation = explain(result, index, precedence);
+
+    TownshipMatch {
+        result_index: index,
+        formatted_address: result.results[index].formatted_address.clone(),
+        source: explanation
+            .chosen
+            .and_then(|i| explanation.components[i].matched.clone()),
+        township: explanation.township,
+    }
+}
+
+/// How the township was picked from one result, step by step, for `--explain`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Explanation {
+    /// The address as it was given.
+    pub input: String,
+    /// The address as it was sent to the provider, after any normalization.
+    pub query: String,
+    pub provider: String,
+    /// Set when an earlier provider in the chain failed and this one answered instead.
+    pub provider_fallback: bool,
+    pub result_index: usize,
+    pub mode: MatchMode,
+    /// The short name of the result's `country` component.
+    pub country: Option<String>,
+    /// Whether the country has its own township types, rather than the default ones being used.
+    pub country_types: bool,
+    /// The township types searched for, most specific first.
+    pub types: Vec<String>,
+    /// Every component of the result, in the provider's order.
+    pub components: Vec<ComponentTrace>,
+    /// Which of `components` the township came from: the one with the most specific of `types`, or the first one
+    /// with any of them under `MatchMode::FirstMatch`. `None` when none had any.
+    pub chosen: Option<usize>,
+    pub township: String,
+    /// The name the component had, if the township was then renamed, like `Springfield` to `Springfield City`.
+    pub renamed_from: Option<String>,
+}
+
+/// One component of a result, and which township type it matched.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentTrace {
+    pub name: String,
+    pub types: Vec<String>,
+    /// The most specific township type the component has.
+    pub matched: Option<String>,
+    /// That type's position in the searched types, from 1.
+    pub precedence: Option<usize>,
+}
+
+/// Traces how the township is picked from the result at `index`, which must exist. This is the logic `get_township`
+/// itself uses, so the trace always agrees with the township written out.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let mut precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+/// // Components listed as level 3 then locality: the locality is more specific, so it wins.
+/// let explanation = explain(&geo_data, 0, &precedence);
+/// assert_eq!(explanation.chosen, Some(1));
+/// assert_eq!(explanation.components[0].precedence, Some(2));
+/// // Going by the provider's order instead, the level 3 area comes first, so it wins despite its precedence.
+/// precedence.mode = MatchMode::FirstMatch;
+/// assert_eq!(explain(&geo_data, 0, &precedence).chosen, Some(0));
+/// ```
+pub fn explain(result: &GeoDataAddress, index: usize, precedence: &Precedence) -> Explanation {
+    let chosen = &result.results[index];
+    let country = country_of(chosen).map(str::to_uppercase);
+    let types = precedence.types_for(chosen);
+
+    let components: Vec<ComponentTrace> = chosen
+        .address_components
+        .iter()
+        .map(|component| {
+            let position = types.iter().position(|t| component.types.iter().any(|c| c == t));
+            ComponentTrace {
+                name: component.long_name.clone(),
+                types: component.types.clone(),
+                matched: position.map(|p| types[p].to_string()),
+                precedence: position.map(|p| p + 1),
+            }
+        })
+        .collect();
+    let picked = match precedence.mode {
+        // The first of the most specific ones, should two components share a type.
+        MatchMode::Strict => components
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| Some((c.precedence?, i)))
+            .min()
+            .map(|(_, i)| i),
+        MatchMode::FirstMatch => components.iter().position(|c| c.matched.is_some()),
+    };
+
+    let mut township = picked.map(|i| components[i].name.clone()).unwrap_or_default();
+    let mut renamed_from = None;
+    if township == "Springfield" {
+        renamed_from = Some(township);
+        township = String::from("Springfield City");
+    }
+
+    Explanation {
+        result_index: index,
+        mode: precedence.mode,
+        country_types: country.as_ref().is_some_and(|c| precedence.countries.contains_key(c)),
+        country,
+        types: types.iter().map(|t| t.to_string()).collect(),
+        components,
+        chosen: picked,
+        township,
+        renamed_from,
+        ..Explanation::default()
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Why {} is in {:?}:", self.input, self.township)?;
+        if self.query != self.input {
+            writeln!(f, "  Looked up as {:?} after normalization", self.query)?;
+        }
+        if self.provider_fallback {
+            writeln!(
+                f,
+                "  Answered by {} after the providers before it failed",
+                self.provider
+            )?;
+        }
+        writeln!(
+            f,
+            "  Result #{} from {}, country {}, using {} township types",
+            self.result_index + 1,
+            self.provider,
+            self.country.as_deref().unwrap_or("unknown"),
+            if self.country_types { "its own" } else { "the default" }
+        )?;
+        writeln!(f, "  Searched in order: {}", self.types.join(", "))?;
+
+        let chosen = self.chosen.and_then(|i| self.components[i].precedence);
+        for (i, component) in self.components.iter().enumerate() {
+            let verdict = match (&component.matched, component.precedence) {
+                (Some(kind), Some(position)) if Some(i) == self.chosen => match self.mode {
+                    MatchMode::Strict => format!("matches {} (#{}), chosen as the most specific match", kind, position),
+                    MatchMode::FirstMatch => format!(
+                        "matches {} (#{}), chosen as the first matching component",
+                        kind, position
+                    ),
+                },
+                (Some(kind), Some(position)) if self.mode == MatchMode::Strict && chosen < Some(position) => {
+                    format!("matches {} (#{}), but a more specific type matched", kind, position)
+                }
+                (Some(kind), Some(position)) => format!("matches {} (#{}), but comes later", kind, position),
+                _ => String::from("no match"),
+            };
+            writeln!(
+                f,
+                "  - {} [{}]: {}",
+                component.name,
+                component.types.join(", "),
+                verdict
+            )?;
+        }
+
+        if self.chosen.is_none() {
+            writeln!(f, "  No component matched, so the township is left empty")?;
+        }
+        if let Some(name) = &self.renamed_from {
+            writeln!(f, "  Renamed from {:?}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The digits Plus Codes are written with.
+const PLUS_CODE_DIGITS: &str = "23456789CFGHJMPQRVWX";
+
+/// Whether a result is just a Plus Code, which Google falls back to when it can't find a street address. These
+/// carry a `plus_code` type, or a formatted address that starts with the code itself, like `9G8F+6W Zürich`.
+pub fn is_plus_code(result: &AddressResult) -> bool {
+    result.types.iter().any(|t| t == "plus_code") || looks_like_plus_code(&result.formatted_address)
+}
+
+/// Whether an address starts with a full or short Plus Code.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// assert!(looks_like_plus_code("8FVC9G8F+6W"));
+/// assert!(looks_like_plus_code("9G8F+6W, Zürich, Switzerland"));
+/// assert!(!looks_like_plus_code("123 Main St, Springfield, OH"));
+/// ```
+fn looks_like_plus_code(address: &str) -> bool {
+    let code = address.split([' ', ',']).next().unwrap_or_default();
+    let (before, after) = match code.split_once('+') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let valid = |part: &str| part.chars().all(|c| PLUS_CODE_DIGITS.contains(c.to_ascii_uppercase()));
+
+    (2..=8).contains(&before.len()) && before.len() % 2 == 0 && after.len() <= 3 && valid(before) && valid(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ok, result};
+
+    const LEVEL_3: &[&str] = &["administrative_area_level_3", "political"];
+    const LOCALITY: &[&str] = &["locality", "political"];
+    const COUNTY: &[&str] = &["administrative_area_level_2", "political"];
+    const COUNTRY: &[&str] = &["country", "political"];
+
+    #[test]
+    fn most_specific_type_wins_wherever_it_is() {
+        let response = ok(vec![result(&[
+            ("Bethel Township", LEVEL_3),
+            ("New Carlisle", LOCALITY),
+            ("Clark County", COUNTY),
+        ])]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        let township = get_township(&response, &precedence).unwrap();
+        assert_eq!(township.township, "New Carlisle");
+        assert_eq!(township.source.as_deref(), Some("locality"));
+        assert_eq!(township.result_index, 0);
+        assert_eq!(
+            township.formatted_address,
+            "Bethel Township, New Carlisle, Clark County"
+        );
+    }
+
+    #[test]
+    fn match_modes_diverge_on_component_order() {
+        let response = ok(vec![result(&[
+            ("Bethel Township", LEVEL_3),
+            ("New Carlisle", LOCALITY),
+            ("Clark County", COUNTY),
+        ])]);
+        let mut precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+        assert_eq!(precedence.mode, MatchMode::Strict);
+
+        let strict = get_township(&response, &precedence).unwrap();
+        assert_eq!(strict.township, "New Carlisle");
+        assert_eq!(strict.source.as_deref(), Some("locality"));
+
+        precedence.mode = MatchMode::FirstMatch;
+        let first = get_township(&response, &precedence).unwrap();
+        assert_eq!(first.township, "Bethel Township");
+        assert_eq!(first.source.as_deref(), Some("administrative_area_level_3"));
+
+        assert_eq!("first-match".parse::<MatchMode>().unwrap(), MatchMode::FirstMatch);
+        assert_eq!("strict".parse::<MatchMode>().unwrap(), MatchMode::Strict);
+        assert!("loose".parse::<MatchMode>().is_err());
+    }
+
+    #[test]
+    fn falls_back_to_less_specific_types() {
+        let response = ok(vec![result(&[("Bethel Township", LEVEL_3), ("Clark County", COUNTY)])]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        let township = get_township(&response, &precedence).unwrap();
+        assert_eq!(township.township, "Bethel Township");
+        assert_eq!(township.source.as_deref(), Some("administrative_area_level_3"));
+    }
+
+    #[test]
+    fn no_matching_component_leaves_the_township_empty() {
+        let response = ok(vec![result(&[("Main Street", &["route"])])]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        let township = get_township(&response, &precedence).unwrap();
+        assert_eq!(township.township, "");
+        assert_eq!(township.source, None);
+    }
+
+    #[test]
+    fn each_country_uses_its_own_types() {
+        let response = ok(vec![
+            result(&[("Ortsteil", LOCALITY), ("Gemeinde", LEVEL_3), ("de", COUNTRY)]),
+            result(&[
+                ("New Carlisle", LOCALITY),
+                ("Bethel Township", LEVEL_3),
+                ("US", COUNTRY),
+            ]),
+            result(&[("Nowhere", LOCALITY), ("Somewhere", LEVEL_3)]),
+        ]);
+        let mut precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+        precedence
+            .countries
+            .insert(String::from("DE"), vec!["administrative_area_level_3"]);
+
+        let all = get_all_townships(&response, &precedence).unwrap();
+        let townships: Vec<&str> = all.iter().map(|t| t.township.as_str()).collect();
+        assert_eq!(townships, ["Gemeinde", "New Carlisle", "Nowhere"]);
+        assert_eq!(all[0].source.as_deref(), Some("administrative_area_level_3"));
+    }
+
+    #[test]
+    fn only_the_first_result_is_used() {
+        let response = ok(vec![
+            result(&[("First Town", LOCALITY)]),
+            result(&[("Second Town", LOCALITY)]),
+        ]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        assert_eq!(get_township(&response, &precedence).unwrap().township, "First Town");
+        let all = get_all_townships(&response, &precedence).unwrap();
+        let townships: Vec<&str> = all.iter().map(|t| t.township.as_str()).collect();
+        assert_eq!(townships, ["First Town", "Second Town"]);
+    }
+
+    #[test]
+    fn address_comes_from_the_result_with_the_township() {
+        let response = ok(vec![
+            result(&[("Main Street", &["route"]), ("Ohio", &["administrative_area_level_1"])]),
+            result(&[("Enon", LOCALITY), ("Clark County", COUNTY)]),
+        ]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        let all = get_all_townships(&response, &precedence).unwrap();
+        assert_eq!(all[0].formatted_address, "Main Street, Ohio");
+        assert_eq!((all[1].township.as_str(), all[1].result_index), ("Enon", 1));
+        assert_eq!(all[1].formatted_address, "Enon, Clark County");
+
+        let record = crate::output::OutputRecord::from_geo_data("1 Main St", &response, all[1].result_index);
+        assert_eq!(record.formatted_address, all[1].formatted_address);
+        assert_eq!(record.county.as_deref(), Some("Clark County"));
+    }
+
+    #[test]
+    fn springfield_is_renamed() {
+        let response = ok(vec![result(&[("Springfield", LOCALITY)])]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        assert_eq!(
+            get_township(&response, &precedence).unwrap().township,
+            "Springfield City"
+        );
+    }
+
+    #[test]
+    fn ok_without_results_is_its_own_miss() {
+        let response = ok(vec![]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        let miss = get_township(&response, &precedence).unwrap_err();
+        assert_eq!(miss, Miss::EmptyResults);
+        assert_eq!(miss.to_string(), "No township found (status OK, but empty results)");
+        assert_eq!(get_all_townships(&response, &precedence), Err(Miss::EmptyResults));
+    }
+
+    #[test]
+    fn unsuccessful_status_is_a_miss() {
+        let response = crate::testing::response(serde_json::json!({ "status": "ZERO_RESULTS", "results": [] }));
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        assert_eq!(
+            get_township(&response, &precedence),
+            Err(Miss::Status(String::from("ZERO_RESULTS")))
+        );
+    }
+
+    #[test]
+    fn plus_codes_are_recognized() {
+        let by_type = |types: &[&str]| {
+            let mut value = result(&[("Bethel", LOCALITY)]);
+            value["types"] = serde_json::json!(types);
+            ok(vec![value]).results.remove(0)
+        };
+        assert!(is_plus_code(&by_type(&["plus_code"])));
+        assert!(!is_plus_code(&by_type(&["street_address"])));
+
+        assert!(looks_like_plus_code("8FVC9G8F+6W"));
+        assert!(looks_like_plus_code("9G8F+6W Zürich, Switzerland"));
+        assert!(looks_like_plus_code("9g8f+, Bethel, OH"));
+        assert!(!looks_like_plus_code("123 Main St, Springfield, OH"));
+        assert!(!looks_like_plus_code("1+1 Main St"));
+        assert!(!looks_like_plus_code("9G8F+6WXYZ Bethel"));
+    }
+
+    #[test]
+    fn trace_follows_the_precedence() {
+        let response = ok(vec![result(&[
+            ("Main Street", &["route"]),
+            ("Bethel Township", LEVEL_3),
+            ("Springfield", LOCALITY),
+            ("Clark County", COUNTY),
+        ])]);
+        let precedence = Precedence::new(DEFAULT_TOWNSHIP_TYPES.to_vec());
+
+        let explanation = Explanation {
+            input: String::from("1 Main St"),
+            query: String::from("1 Main St"),
+            provider: String::from("google"),
+            ..explain(&response, 0, &precedence)
+        };
+        let traced: Vec<(Option<&str>, Option<usize>)> = explanation
+            .components
+            .iter()
+            .map(|c| (c.matched.as_deref(), c.precedence))
+            .collect();
+        assert_eq!(
+            traced,
+            [
+                (None, None),
+                (Some("administrative_area_level_3"), Some(2)),
+                (Some("locality"), Some(1)),
+                (Some("administrative_area_level_2"), Some(3)),
+            ]
+        );
+        assert_eq!(explanation.chosen, Some(2));
+        assert_eq!(explanation.township, "Springfield City");
+        assert_eq!(explanation.renamed_from.as_deref(), Some("Springfield"));
+        assert_eq!(
+            explanation.to_string(),
+            "Why 1 Main St is in \"Springfield City\":\n\
+             \x20 Result #1 from google, country unknown, using the default township types\n\
+             \x20 Searched in order: locality, administrative_area_level_3, administrative_area_level_2\n\
+             \x20 - Main Street [route]: no match\n\
+             \x20 - Bethel Township [administrative_area_level_3, political]: matches administrative_area_level_3 (#2), \
+             but a more specific type matched\n\
+             \x20 - Springfield [locality, political]: matches locality (#1), chosen as the most specific match\n\
+             \x20 - Clark County [administrative_area_level_2, political]: matches administrative_area_level_2 (#3), \
+             but a more specific type matched\n\
+             \x20 Renamed from \"Springfield\"\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::cli::Opts;
+use crate::{dedup, input, preview};
+use anyhow::{bail, Error};
+use std::fs;
+use std::path::PathBuf;
+
+/// Something wrong with one row of an input file.
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    /// The line of a text file, or the record of a CSV file not counting its header, starting from 1.
+    pub row: usize,
+    pub problem: String,
+    pub value: String,
+}
+
+/// Checks every input file for rows that would fail or waste a request, prints what it found and stops there,
+/// without geocoding anything. Errors when there's anything to fix, so it can gate a scripted run.
+pub fn run(opts: &Opts, paths: &[PathBuf]) -> Result<(), Error> {
+    let column = opts.address_column();
+    let mut rows = vec![];
+
+    for path in paths {
+        let bytes = fs::read(path)?;
+        let mut findings = check_encoding(&bytes);
+        if findings.is_empty() {
+            let addresses = input::read_file(path, &column, opts.auto_skip_header)?;
+            findings = check_addresses(&addresses, opts.dedup_normalization);
+            log!(
+                "{}: {} addresses, {} unique, {} problems",
+                path.display(),
+                addresses.len(),
+                dedup::dedup(&addresses, opts.dedup_normalization).unique.len(),
+                findings.len()
+            );
+        } else {
+            log!("{}: {} lines aren't valid UTF-8", path.display(), findings.len());
+        }
+
+        rows.extend(
+            findings
+                .into_iter()
+                .map(|f| vec![path.display().to_string(), f.row.to_string(), f.problem, f.value]),
+        );
+    }
+
+    if rows.is_empty() {
+        log!("No problems found, the input is ready to geocode.");
+        return Ok(());
+    }
+
+    preview::print_table(&["File", "Row", "Problem", "Value"], &rows);
+    bail!("Found {} problems in the input", rows.len())
+}
+
+/// Reports the lines that aren't valid UTF-8. The addresses themselves can't be read until these are fixed.
+fn check_encoding(bytes: &[u8]) -> Vec<Finding> {
+    bytes
+        .split(|b| *b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| std::str::from_utf8(line).is_err())
+        .map(|(i, line)| Finding {
+            row: i + 1,
+            problem: String::from("Not valid UTF-8, save the file as UTF-8 and check this line"),
+            value: String::from_utf8_lossy(line).trim_end().to_string(),
+        })
+        .collect()
+}
+
+/// Reports blank rows, rows that can't be addresses, stray characters and duplicates under `policy`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// let addresses = vec![String::from("1 Main St"), String::from(""), String::from("1 main st"), String::from("42")];
+/// let problems: Vec<String> = check_addresses(&addresses, Normalization::CaseInsensitive)
+///     .into_iter()
+///     .map(|f| f.problem)
+///     .collect();
+/// assert_eq!(problems, ["Blank", "No letters, so not an address", "Duplicate of row 1"]);
+/// ```
+fn check_addresses(addresses: &[String], policy: dedup::Normalization) -> Vec<Finding> {
+    let deduped = dedup::dedup(addresses, policy);
+    let mut first_rows = vec![None; deduped.unique.len()];
+    let mut findings = vec![];
+
+    for (i, (address, &unique_index)) in addresses.iter().zip(&deduped.mapping).enumerate() {
+        let mut report = |problem: String| {
+            findings.push(Finding {
+                row: i + 1,
+                problem,
+                value: address.clone(),
+            })
+        };
+
+        if address.trim().is_empty() {
+            report(String::from("Blank"));
+            continue;
+        }
+        if !address.chars().any(char::is_alphabetic) {
+            report(String::from("No letters, so not an address"));
+        }
+        if address.starts_with('\u{feff}') {
+            report(String::from("Starts with a byte order mark"));
+        }
+        if address.chars().any(|c| c.is_control() && c != '\t' || c == '\u{fffd}') {
+            report(String::from("Contains control or replacement characters"));
+        }
+        match first_rows[unique_index] {
+            Some(first) => report(format!("Duplicate of row {}", first)),
+            None => first_rows[unique_index] = Some(i + 1),
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dedup::Normalization;
+
+    fn problems(addresses: &[&str]) -> Vec<(usize, String)> {
+        let addresses: Vec<String> = addresses.iter().map(|a| a.to_string()).collect();
+        check_addresses(&addresses, Normalization::CaseInsensitive)
+            .into_iter()
+            .map(|f| (f.row, f.problem))
+            .collect()
+    }
+
+    #[test]
+    fn clean_addresses_have_no_findings() {
+        assert!(problems(&["1 Main St", "2 Oak Ave", "Apt 3\t4 Elm Rd"]).is_empty());
+    }
+
+    #[test]
+    fn blank_rows_are_reported() {
+        assert_eq!(
+            problems(&["1 Main St", "", "  "]),
+            [(2, "Blank".into()), (3, "Blank".into())]
+        );
+    }
+
+    #[test]
+    fn rows_without_letters_are_reported() {
+        assert_eq!(
+            problems(&["12345", "1 Main St"]),
+            [(1, String::from("No letters, so not an address"))]
+        );
+    }
+
+    #[test]
+    fn stray_characters_are_reported() {
+        assert_eq!(
+            problems(&["\u{feff}1 Main St", "2 Oak\u{7} Ave", "3 Elm \u{fffd}Rd"]),
+            [
+                (1, String::from("Starts with a byte order mark")),
+                (2, String::from("Contains control or replacement characters")),
+                (3, String::from("Contains control or replacement characters")),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_point_back_to_the_first_row() {
+        assert_eq!(
+            problems(&["1 Main St", "2 Oak Ave", "1 MAIN ST", "1 main st"]),
+            [
+                (3, String::from("Duplicate of row 1")),
+                (4, String::from("Duplicate of row 1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_lines_are_reported() {
+        let findings = check_encoding(b"1 Main St\n2 Oak \xff Ave\n3 Elm Rd\n");
+        assert_eq!(
+            findings,
+            [Finding {
+                row: 2,
+                problem: String::from("Not valid UTF-8, save the file as UTF-8 and check this line"),
+                value: String::from("2 Oak \u{fffd} Ave"),
+            }]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::cache::{Cache, CacheKey};
+use crate::cli::Opts;
+use crate::preview;
+use crate::township::{get_township, Precedence};
+use crate::{fetch_with_retries, GeoDataAddress};
+use anyhow::{anyhow, Error};
+use std::path::Path;
+
+/// A cached address whose township no longer matches a fresh lookup.
+#[derive(Debug, PartialEq)]
+pub struct Discrepancy {
+    pub provider: String,
+    pub address: String,
+    pub cached: String,
+    pub fresh: String,
+}
+
+/// Re-fetches cached responses and prints the addresses whose township has changed.
+///
+/// Only entries cached by one of the configured providers, with the same query parameters, are checked. The cache
+/// itself is left untouched so the report can inform whether to throw it away.
+pub async fn run(opts: &Opts) -> Result<(), Error> {
+    let path = opts
+        .cache
+        .as_deref()
+        .ok_or_else(|| anyhow!("--verify needs a --cache to check"))?;
+    let cache = Cache::open_read_only(path)?;
+    let geocoders = opts.geocoders(&opts.credentials())?;
+    let precedence = opts.precedence();
+    let budget = opts.retry_budget()?;
+    let client = opts.client()?;
+
+    let mut keys: Vec<(usize, CacheKey)> = cache
+        .keys()
+        .filter_map(|key| {
+            let index = geocoders
+                .iter()
+                .position(|g| CacheKey::new(g.as_ref(), &key.address) == *key)?;
+            Some((index, key.clone()))
+        })
+        .collect();
+    keys.sort_by(|a, b| a.1.address.cmp(&b.1.address));
+    if let Some(size) = opts.verify_sample {
+        keys = preview::sample(&keys, size, opts.seed);
+    }
+
+    log!("Verifying {} cached responses...", keys.len());
+
+    let mut discrepancies = vec![];
+    for (index, key) in &keys {
+        let geocoder = geocoders[*index].as_ref();
+        let cached = cache.get(key).expect("key came from the cache");
+        let fresh = match fetch_with_retries(&client, geocoder, &key.address, opts.max_retries, &budget).await {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                log!("Couldn't re-fetch {}: {}", key.address, e);
+                continue;
+            }
+        };
+
+        if let Some(discrepancy) = compare(key, cached, &fresh, &precedence) {
+            discrepancies.push(discrepancy);
+        }
+    }
+
+    if discrepancies.is_empty() {
+        log!("All {} verified townships still match.", keys.len());
+        return Ok(());
+    }
+
+    log!("{} of {} townships have changed:", discrepancies.len(), keys.len());
+    let rows: Vec<Vec<String>> = discrepancies
+        .into_iter()
+        .map(|d| vec![d.provider, d.address, d.cached, d.fresh])
+        .collect();
+    preview::print_table(&["Provider", "Address", "Cached Township", "Fresh Township"], &rows);
+
+    Ok(())
+}
+
+/// Compares two cache files and prints the addresses whose township differs between them, using no network at all.
+///
+/// Entries are matched up by their whole key, so the same address cached by another provider or with other query
+/// parameters is treated as a different entry. Entries only in one of the files are counted but not compared.
+pub fn diff(old: &Path, new: &Path, precedence: &Precedence) -> Result<(), Error> {
+    let (old_cache, new_cache) = (Cache::open_read_only(old)?, Cache::open_read_only(new)?);
+
+    let mut keys: Vec<&CacheKey> = old_cache.keys().filter(|key| new_cache.get(key).is_some()).collect();
+    keys.sort_by(|a, b| a.address.cmp(&b.address));
+    let only_old = old_cache.keys().count() - keys.len();
+    let only_new = new_cache.keys().count() - keys.len();
+
+    let discrepancies: Vec<Discrepancy> = keys
+        .iter()
+        .filter_map(|key| {
+            let (before, after) = (old_cache.get(key)?, new_cache.get(key)?);
+            compare(key, before, after, precedence)
+        })
+        .collect();
+
+    log!(
+        "Compared {} addresses in both caches, {} only in {}, {} only in {}.",
+        keys.len(),
+        only_old,
+        old.display(),
+        only_new,
+        new.display()
+    );
+    if discrepancies.is_empty() {
+        log!("No townships changed.");
+        return Ok(());
+    }
+
+    log!("{} townships changed:", discrepancies.len());
+    let rows: Vec<Vec<String>> = discrepancies
+        .into_iter()
+        .map(|d| vec![d.provider, d.address, d.cached, d.fresh])
+        .collect();
+    preview::print_table(&["Provider", "Address", "Old Township", "New Township"], &rows);
+
+    Ok(())
+}
+
+/// Compares the township picked from a cached response with the one picked from a fresh response.
+pub fn compare(
+    key: &CacheKey,
+    cached: &GeoDataAddress,
+    fresh: &GeoDataAddress,
+    precedence: &Precedence,
+) -> Option<Discrepancy> {
+    let describe = |response: &GeoDataAddress| match get_township(response, precedence) {
+        Ok(found) => found.township,
+        Err(miss) => miss.to_string(),
+    };
+    let (cached, fresh) = (describe(cached), describe(fresh));
+
+    if cached == fresh {
+        return None;
+    }
+
+    Some(Discrepancy {
+        provider: key.provider.clone(),
+        address: key.address.clone(),
+        cached,
+        fresh,
+    })
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{google_result, run, run_ok, MockServer, Response};
+use serde_json::json;
+use std::net::TcpListener;
+use std::path::Path;
+
+/// The Check, Result and Detail cells of each row of the check table.
+fn checks(stdout: &[u8]) -> Vec<Vec<String>> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter(|line| line.starts_with("google "))
+        .map(|line| line.split('|').skip(1).map(|cell| cell.trim().to_string()).collect())
+        .collect()
+}
+
+fn check(dir: &Path, base_url: &str) -> std::process::Output {
+    run(dir, &["--base-url", base_url, "check"])
+}
+
+#[test]
+fn check_passes_against_a_working_endpoint() {
+    let server =
+        MockServer::start(|request| Response::json(google_result(&request.query("address").unwrap(), "Washington")));
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run_ok(dir.path(), &["--base-url", &server.url, "check"]);
+
+    let rows = checks(&output.stdout);
+    assert_eq!(rows[0][..2], ["Credentials", "PASS"]);
+    assert_eq!(rows[0][2], "An API key is set, and accepted");
+    assert_eq!(rows[1][..2], ["Reachable", "PASS"]);
+    assert_eq!(rows[2][..2], ["Quota", "PASS"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("All 3 checks passed, ready to geocode."));
+    assert_eq!(server.requests()[0].query("key").unwrap(), "test-key");
+}
+
+#[test]
+fn unreachable_endpoint_fails_the_check() {
+    // Nothing listens on a port that was just given back.
+    let url = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let erroring = MockServer::start(|_| Response::status(500));
+    let dir = tempfile::tempdir().unwrap();
+
+    for base_url in [url.as_str(), erroring.url.as_str()] {
+        let output = check(dir.path(), base_url);
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 3 checks failed"));
+        let rows = checks(&output.stdout);
+        assert_eq!(
+            rows[0],
+            ["Credentials", "PASS", "An API key is set, but it couldn't be tried"]
+        );
+        assert_eq!(rows[1][..2], ["Reachable", "FAIL"]);
+        assert_eq!(rows[2], ["Quota", "SKIPPED", "The endpoint couldn't be reached"]);
+    }
+}
+
+#[test]
+fn denied_key_fails_the_credentials_check() {
+    let server = MockServer::start(|_| {
+        Response::json(json!({
+            "status": "REQUEST_DENIED",
+            "error_message": "The provided API key is invalid.",
+            "results": [],
+        }))
+    });
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = check(dir.path(), &server.url);
+
+    assert!(!output.status.success());
+    let rows = checks(&output.stdout);
+    assert_eq!(rows[0], ["Credentials", "FAIL", "The provided API key is invalid."]);
+    assert_eq!(rows[1][..2], ["Reachable", "PASS"]);
+    assert_eq!(rows[2][..2], ["Quota", "SKIPPED"]);
+}
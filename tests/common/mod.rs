@@ -0,0 +1,281 @@
+//! A canned HTTP server to stand in for the providers, and a way to run the binary against it.
+
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A request the mock server received.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// The path with its query string.
+    pub target: String,
+    /// Every header, with its name lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The decoded value of the query parameter `name`.
+    pub fn query(&self, name: &str) -> Option<String> {
+        let (_, query) = self.target.split_once('?')?;
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some(decode(value)).filter(|_| decode(key) == name)
+        })
+    }
+
+    /// The value of the header `name`, which must be lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("the request body is JSON")
+    }
+}
+
+/// Decodes a form-urlencoded query value.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+/// A response for the mock server to send.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(body: Value) -> Response {
+        Response {
+            status: 200,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub fn html(body: &str) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/html",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn status(status: u16) -> Response {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: vec![],
+        }
+    }
+}
+
+/// Answers every request with whatever its handler returns, keeping each request to check later.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler = Arc::new(handler);
+
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (handler, received) = (handler.clone(), received.clone());
+                thread::spawn(move || {
+                    if let Some(request) = read_request(&stream) {
+                        let response = handler(&request);
+                        received.lock().unwrap().push(request);
+                        write_response(stream, &response);
+                    }
+                });
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// Every request received so far, in the order they were answered.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = (parts.next()?.to_string(), parts.next()?.to_string());
+
+    let (mut length, mut chunked, mut headers) = (0, false, vec![]);
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        let (name, value) = (name.to_lowercase(), value.trim().to_string());
+        match name.as_str() {
+            "content-length" => length = value.parse().ok()?,
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+        headers.push((name, value));
+    }
+
+    let mut body = vec![];
+    if chunked {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).ok()?;
+            let size = usize::from_str_radix(size.trim(), 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else {
+        body.resize(length, 0);
+        reader.read_exact(&mut body).ok()?;
+    }
+
+    Some(Request {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) {
+    let head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    // The client may hang up early, like when a body is too large.
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&response.body);
+}
+
+/// A Google response with one result in `township`, Ohio.
+pub fn google_result(address: &str, township: &str) -> Value {
+    json!({
+        "status": "OK",
+        "results": [{
+            "formatted_address": format!("{}, {}, OH, USA", address, township),
+            "place_id": format!("place-{}", address),
+            "geometry": { "location": { "lat": 39.9, "lng": -84.0 } },
+            "address_components": [
+                { "long_name": township, "short_name": township, "types": ["locality", "political"] },
+                { "long_name": "Clark County", "short_name": "Clark County", "types": ["administrative_area_level_2", "political"] },
+                { "long_name": "Ohio", "short_name": "OH", "types": ["administrative_area_level_1", "political"] },
+                { "long_name": "United States", "short_name": "US", "types": ["country", "political"] },
+            ],
+        }],
+    })
+}
+
+pub fn google_zero_results() -> Value {
+    json!({ "status": "ZERO_RESULTS", "results": [] })
+}
+
+/// A Nominatim search response with one place in `town`.
+pub fn nominatim_place(town: &str) -> Value {
+    json!([{
+        "place_id": 42,
+        "lat": "39.9",
+        "lon": "-84.0",
+        "display_name": format!("{}, Clark County, Ohio, United States", town),
+        "addresstype": "town",
+        "address": {
+            "town": town,
+            "county": "Clark County",
+            "state": "Ohio",
+            "ISO3166-2-lvl4": "US-OH",
+            "country": "United States",
+            "country_code": "us",
+        },
+    }])
+}
+
+/// Runs the binary in `dir` with `args`, a Google API key and nothing on stdin.
+pub fn run(dir: &Path, args: &[&str]) -> Output {
+    run_with_env(dir, args, &[])
+}
+
+/// Runs the binary like `run`, with the environment variables `vars` set as well.
+pub fn run_with_env(dir: &Path, args: &[&str], vars: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_township-rs"))
+        .current_dir(dir)
+        .args(args)
+        .env("GOOGLE_API_KEY", "test-key")
+        .env_remove("API_KEY")
+        .envs(vars.iter().copied())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+/// Runs the binary like `run`, failing the test when it doesn't succeed.
+pub fn run_ok(dir: &Path, args: &[&str]) -> Output {
+    let output = run(dir, args);
+    assert!(
+        output.status.success(),
+        "township-rs {:?} failed:\n{}{}",
+        args,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// The rows of a CSV file, header first.
+pub fn read_csv(path: &Path) -> Vec<Vec<String>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path(path)
+        .unwrap()
+        .records()
+        .map(|record| record.unwrap().iter().map(str::to_string).collect())
+        .collect()
+}
@@ -0,0 +1,597 @@
+mod common;
+
+use arrow_array::{Array, Float64Array, StringArray};
+use common::{google_result, google_zero_results, run, run_ok, run_with_env, MockServer, Response};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Read;
+
+fn google() -> MockServer {
+    MockServer::start(|request| {
+        let address = request.query("address").unwrap();
+        Response::json(google_result(&address, "Springfield"))
+    })
+}
+
+#[test]
+fn parquet_reads_back_with_its_schema() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--format",
+            "parquet",
+            "--output",
+            "results.parquet",
+            "--base-url",
+            &server.url,
+        ],
+    );
+
+    let file = File::open(dir.path().join("results.parquet")).unwrap();
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+    let fields: Vec<&str> = builder.schema().fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(
+        fields,
+        [
+            "address",
+            "formatted_address",
+            "township",
+            "county",
+            "state",
+            "lat",
+            "lng",
+            "place_id",
+            "status",
+            "provider",
+            "confidence"
+        ]
+    );
+    let column = builder.metadata().row_group(0).column(0);
+    assert!(matches!(column.compression(), Compression::SNAPPY));
+
+    let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+    let batch = &batches[0];
+    let strings = |name: &str| {
+        batch
+            .column_by_name(name)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .clone()
+    };
+    let floats = |name: &str| {
+        batch
+            .column_by_name(name)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap()
+            .clone()
+    };
+
+    assert_eq!(strings("address").value(0), "1 Main St");
+    assert_eq!(strings("formatted_address").value(0), "1 Main St, Springfield, OH, USA");
+    assert_eq!(strings("township").value(0), "Springfield City");
+    assert_eq!(strings("county").value(0), "Clark County");
+    assert_eq!(strings("state").value(0), "OH");
+    assert_eq!(floats("lat").value(0), 39.9);
+    assert_eq!(floats("lng").value(0), -84.0);
+    assert_eq!(strings("place_id").value(0), "place-1 Main St");
+    assert_eq!(strings("status").value(0), "OK");
+    assert_eq!(strings("provider").value(0), "google");
+    assert!(floats("confidence").is_null(0));
+}
+
+#[test]
+fn json_full_round_trips_every_response() {
+    let server = MockServer::start(|request| match request.query("address").unwrap().as_str() {
+        "nowhere" => Response::json(google_zero_results()),
+        address => {
+            let mut response = google_result(address, "Bethel");
+            response["results"][0]["plus_code"] = json!({ "global_code": "86FRXXXX+XX" });
+            Response::json(response)
+        }
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\nnowhere\n1 Main St").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--format",
+            "json-full",
+            "--output",
+            "full.json",
+            "--base-url",
+            &server.url,
+        ],
+    );
+
+    let written: Value = serde_json::from_str(&fs::read_to_string(dir.path().join("full.json")).unwrap()).unwrap();
+    let mut expected = google_result("1 Main St", "Bethel");
+    expected["results"][0]["plus_code"] = json!({ "global_code": "86FRXXXX+XX" });
+    assert_eq!(
+        written,
+        json!({ "1 Main St": expected, "nowhere": google_zero_results() })
+    );
+}
+
+#[test]
+fn every_format_gets_the_same_records() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--format",
+            "csv,geojson",
+            "--output",
+            "results",
+            "--base-url",
+            &server.url,
+        ],
+    );
+
+    let csv = common::read_csv(&dir.path().join("results.csv"));
+    let geojson: Value =
+        serde_json::from_str(&fs::read_to_string(dir.path().join("results.geojson")).unwrap()).unwrap();
+    let features = geojson["features"].as_array().unwrap();
+    assert_eq!(csv.len() - 1, features.len());
+    for (row, feature) in csv[1..].iter().zip(features) {
+        assert_eq!(feature["properties"]["formatted_address"], row[0].as_str());
+        assert_eq!(feature["properties"]["township"], row[1].as_str());
+    }
+}
+
+#[test]
+fn mismatched_outputs_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let too_few = common::run(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--format",
+            "csv,geojson,json-full",
+            "--output",
+            "a.csv",
+            "--output",
+            "b.geojson",
+        ],
+    );
+    assert!(!too_few.status.success());
+    assert!(String::from_utf8_lossy(&too_few.stderr)
+        .contains("Got 2 --output paths for 3 formats, expected one per format or a single base name"));
+
+    let same_path = common::run(dir.path(), &["--input", "input.txt", "--format", "csv,csv"]);
+    assert!(!same_path.status.success());
+    assert!(String::from_utf8_lossy(&same_path.stderr).contains("More than one format would be written to output.csv"));
+    assert!(!dir.path().join("output.csv").exists());
+}
+
+#[test]
+fn output_is_uploaded_to_an_http_endpoint() {
+    let server = google();
+    let upload = MockServer::start(|_| Response::status(200));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--output",
+            &format!("{}/runs/results.csv", upload.url),
+        ],
+    );
+
+    let requests = upload.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "PUT");
+    assert_eq!(requests[0].target, "/runs/results.csv");
+    // Uploaded with the same client as the lookups.
+    assert!(requests[0].header("user-agent").unwrap().starts_with("township-rs/"));
+    assert_eq!(
+        String::from_utf8_lossy(&requests[0].body),
+        "Address,Township\n\
+         \"1 Main St, Springfield, OH, USA\",Springfield City\n\
+         \"2 Oak Ave, Springfield, OH, USA\",Springfield City\n"
+    );
+}
+
+#[test]
+fn output_is_uploaded_to_s3_signed() {
+    let server = google();
+    let s3 = MockServer::start(|_| Response::status(200));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let output = run_with_env(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--output",
+            "s3://bucket/runs/results.csv",
+        ],
+        &[
+            ("AWS_ACCESS_KEY_ID", "test-id"),
+            ("AWS_SECRET_ACCESS_KEY", "test-secret"),
+            ("AWS_REGION", "us-east-2"),
+            ("AWS_ENDPOINT_URL", &s3.url),
+        ],
+    );
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let requests = s3.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.target, "/bucket/runs/results.csv");
+    assert!(request.header("user-agent").unwrap().starts_with("township-rs/"));
+    assert_eq!(
+        String::from_utf8_lossy(&request.body),
+        "Address,Township\n\"1 Main St, Springfield, OH, USA\",Springfield City\n"
+    );
+
+    let payload_hash: String = Sha256::digest(&request.body)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(request.header("x-amz-content-sha256"), Some(payload_hash.as_str()));
+    let date = &request.header("x-amz-date").unwrap()[..8];
+    let authorization = request.header("authorization").unwrap();
+    let expected = format!(
+        "AWS4-HMAC-SHA256 Credential=test-id/{}/us-east-2/s3/aws4_request, \
+         SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature=",
+        date
+    );
+    assert!(authorization.starts_with(&expected), "{}", authorization);
+    assert_eq!(authorization.len(), expected.len() + 64, "{}", authorization);
+}
+
+#[test]
+fn stdout_output_keeps_the_log_out() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let output = run_ok(
+        dir.path(),
+        &["--input", "input.txt", "--base-url", &server.url, "--output", "-"],
+    );
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Address,Township\n\"1 Main St, Springfield, OH, USA\",Springfield City\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Resolved 1 addresses, 0 failed."));
+}
+
+#[test]
+fn trim_formatted_address_strips_only_the_end() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St, USA Plaza").unwrap();
+    let args = ["--input", "input.txt", "--base-url", &server.url];
+
+    run_ok(dir.path(), &args);
+    assert_eq!(
+        common::read_csv(&dir.path().join("output.csv"))[1][0],
+        "1 Main St, USA Plaza, Springfield, OH, USA"
+    );
+
+    run_ok(
+        dir.path(),
+        &[&args[..], &["--trim-formatted-address", ", USA"]].concat(),
+    );
+    assert_eq!(
+        common::read_csv(&dir.path().join("output.csv"))[1][0],
+        "1 Main St, USA Plaza, Springfield, OH"
+    );
+}
+
+#[test]
+fn input_and_formatted_addresses_are_separate_columns() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    let args = ["--input", "input.txt", "--base-url", &server.url, "--input-address"];
+
+    run_ok(dir.path(), &args);
+    assert_eq!(
+        common::read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Input Address", "Address", "Township"],
+            vec!["1 Main St", "1 Main St, Springfield, OH, USA", "Springfield City"],
+            vec!["2 Oak Ave", "2 Oak Ave, Springfield, OH, USA", "Springfield City"],
+        ]
+    );
+
+    run_ok(dir.path(), &[&args[..], &["--no-formatted-address"]].concat());
+    assert_eq!(
+        common::read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Input Address", "Township"],
+            vec!["1 Main St", "Springfield City"],
+            vec!["2 Oak Ave", "Springfield City"],
+        ]
+    );
+}
+
+#[test]
+fn parallel_files_write_their_own_outputs() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("north.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    fs::write(dir.path().join("south.txt"), "3 Elm Rd").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "north.txt",
+            "--input",
+            "south.txt",
+            "--parallel-files",
+            "--base-url",
+            &server.url,
+        ],
+    );
+
+    assert_eq!(
+        common::read_csv(&dir.path().join("north.output.csv")),
+        [
+            vec!["Address", "Township"],
+            vec!["1 Main St, Springfield, OH, USA", "Springfield City"],
+            vec!["2 Oak Ave, Springfield, OH, USA", "Springfield City"],
+        ]
+    );
+    assert_eq!(
+        common::read_csv(&dir.path().join("south.output.csv")),
+        [
+            vec!["Address", "Township"],
+            vec!["3 Elm Rd, Springfield, OH, USA", "Springfield City"],
+        ]
+    );
+    assert!(dir.path().join("north.failures.csv").exists());
+    assert!(!dir.path().join("output.csv").exists());
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn manifest_records_the_run_without_its_key() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave\n1 main st").unwrap();
+    fs::write(
+        dir.path().join("township.toml"),
+        "google_api_key = \"config-secret\"\nmax-retries = 5\n",
+    )
+    .unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--config",
+            "township.toml",
+            "--manifest",
+            "manifest.json",
+        ],
+    );
+
+    assert_eq!(server.requests()[0].query("key").unwrap(), "config-secret");
+    let text = fs::read_to_string(dir.path().join("manifest.json")).unwrap();
+    assert!(
+        !text.contains("config-secret") && !text.contains("test-key"),
+        "{}",
+        text
+    );
+    let manifest: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(manifest["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(manifest["providers"], json!(["google"]));
+    assert_eq!(manifest["inputs"][0]["path"], "input.txt");
+    assert_eq!(
+        manifest["inputs"][0]["sha256"],
+        "f92e4f79b602ab175fc6d6a86da8912bdcdd69ccd2b3a03d0f734fb2141e897f"
+    );
+    assert_eq!(manifest["addresses"], 3);
+    assert_eq!(manifest["unique_addresses"], 2);
+    assert_eq!(manifest["resolved"], 3);
+    assert_eq!(manifest["failed"], 0);
+    assert!(manifest["started"].as_str().unwrap() <= manifest["finished"].as_str().unwrap());
+    assert_eq!(manifest["options"]["max_retries"], 5);
+}
+
+/// The decompressed contents of the gzip file at `path`.
+fn gunzip(path: &std::path::Path) -> String {
+    let mut contents = String::new();
+    flate2::read::GzDecoder::new(File::open(path).unwrap())
+        .read_to_string(&mut contents)
+        .unwrap();
+    contents
+}
+
+#[test]
+fn gzipped_outputs_read_back_intact() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    let args = ["--input", "input.txt", "--base-url", &server.url];
+
+    run_ok(dir.path(), &args);
+    let plain_csv = fs::read_to_string(dir.path().join("output.csv")).unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            &args[..],
+            &["--gzip", "--format", "csv,geojson,parquet", "--output", "results"],
+        ]
+        .concat(),
+    );
+    assert_eq!(gunzip(&dir.path().join("results.csv.gz")), plain_csv);
+    let geojson: Value = serde_json::from_str(&gunzip(&dir.path().join("results.geojson.gz"))).unwrap();
+    assert_eq!(geojson["features"].as_array().unwrap().len(), 2);
+    assert!(dir.path().join("results.parquet").exists());
+    assert!(!dir.path().join("results.csv").exists());
+
+    // A .gz output is compressed without --gzip.
+    run_ok(dir.path(), &[&args[..], &["--output", "named.csv.gz"]].concat());
+    assert_eq!(gunzip(&dir.path().join("named.csv.gz")), plain_csv);
+}
+
+#[test]
+fn region_centroids_are_kept_and_flagged() {
+    let server = MockServer::start(|request| {
+        let address = request.query("address").unwrap();
+        let mut response = google_result(&address, "Bethel");
+        let location_type = if address == "Clark County" {
+            "APPROXIMATE"
+        } else {
+            "ROOFTOP"
+        };
+        response["results"][0]["geometry"]["location_type"] = json!(location_type);
+        Response::json(response)
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\nClark County").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--region-centroid",
+            "--coord-precision",
+            "2",
+        ],
+    );
+
+    assert_eq!(
+        common::read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Address", "Township", "Lat", "Lng", "Coord Precision"],
+            vec!["1 Main St, Bethel, OH, USA", "Bethel", "39.90", "-84.00", "precise"],
+            vec![
+                "Clark County, Bethel, OH, USA",
+                "Bethel",
+                "39.90",
+                "-84.00",
+                "approximate"
+            ],
+        ]
+    );
+}
+
+#[test]
+fn renamed_fields_appear_in_the_geojson() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--format",
+            "geojson",
+            "--rename-field",
+            "formatted_address=full_address",
+            "--rename-field",
+            "township = town",
+        ],
+    );
+
+    let geojson: Value = serde_json::from_str(&fs::read_to_string(dir.path().join("output.geojson")).unwrap()).unwrap();
+    let properties = geojson["features"][0]["properties"].as_object().unwrap();
+    assert_eq!(properties["full_address"], "1 Main St, Springfield, OH, USA");
+    assert_eq!(properties["town"], "Springfield City");
+    assert_eq!(properties["county"], "Clark County");
+    assert!(!properties.contains_key("formatted_address") && !properties.contains_key("township"));
+
+    let unknown = common::run(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--format",
+            "geojson",
+            "--rename-field",
+            "full=address",
+        ],
+    );
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("Unknown field 'full', expected one of"));
+}
+
+#[test]
+fn unwritable_records_are_skipped_when_allowed() {
+    let server = MockServer::start(|request| {
+        let address = request.query("address").unwrap();
+        let mut body = google_result(&address, "Springfield");
+        if address == "off the map" {
+            body["results"][0]["geometry"]["location"]["lat"] = json!(123.0);
+        }
+        Response::json(body)
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\noff the map\n2 Oak Ave").unwrap();
+    let args = ["--input", "input.txt", "--base-url", &server.url, "--format", "geojson"];
+
+    // The GeoJSON writer won't write a point off the globe, which fails the run by default.
+    let output = run(dir.path(), &args);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("123, -84 isn't a valid location for off the map"));
+
+    let output = run_ok(dir.path(), &[&args[..], &["--continue-on-write-error"]].concat());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Skipping the record for off the map"), "{}", stdout);
+    assert!(
+        stdout.contains("1 records couldn't be written and were skipped."),
+        "{}",
+        stdout
+    );
+
+    let geojson: Value = serde_json::from_str(&fs::read_to_string(dir.path().join("output.geojson")).unwrap()).unwrap();
+    let addresses: Vec<_> = geojson["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|feature| feature["properties"]["address"].as_str().unwrap())
+        .collect();
+    assert_eq!(addresses, ["1 Main St", "2 Oak Ave"]);
+}
@@ -0,0 +1,294 @@
+mod common;
+
+use common::{google_result, read_csv, run_ok, MockServer, Response};
+use serde_json::{json, Value};
+use std::fs;
+
+#[test]
+fn oversized_response_is_a_failure() {
+    let server = MockServer::start(|request| {
+        let address = request.query("address").unwrap();
+        let mut response = google_result(&address, "Bethel");
+        response["padding"] = json!("x".repeat(10_000));
+        Response::json(response)
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--max-response-bytes",
+            "1000",
+        ],
+    );
+
+    // Too large is never retried.
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(
+        read_csv(&dir.path().join("failures.csv")),
+        [
+            vec!["Address", "Reason"],
+            vec!["1 Main St", "Response body exceeded the 1000 byte limit"]
+        ]
+    );
+    assert_eq!(read_csv(&dir.path().join("output.csv")), [vec!["Address", "Township"]]);
+}
+
+#[test]
+fn falls_back_to_the_next_provider() {
+    let google = MockServer::start(|request| match request.query("address").unwrap().as_str() {
+        "1 Main St" => Response::json(google_result("1 Main St", "Bethel")),
+        "2 Oak Ave" => Response::json(common::google_zero_results()),
+        // Answers, but with nothing that counts as a township.
+        _ => Response::json(json!({
+            "status": "OK",
+            "results": [{
+                "formatted_address": "Ohio, USA",
+                "address_components": [
+                    { "long_name": "Ohio", "short_name": "OH", "types": ["administrative_area_level_1", "political"] },
+                ],
+            }],
+        })),
+    });
+    let nominatim = MockServer::start(|_| Response::json(common::nominatim_place("Enon")));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave\n3 Elm Rd").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--provider",
+            "google,nominatim",
+            "--base-url",
+            &format!("google={}", google.url),
+            "--base-url",
+            &format!("nominatim={}", nominatim.url),
+        ],
+    );
+
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Address", "Township", "Provider"],
+            vec!["1 Main St, Bethel, OH, USA", "Bethel", "google"],
+            vec!["Enon, Clark County, Ohio, United States", "Enon", "nominatim"],
+            vec!["Enon, Clark County, Ohio, United States", "Enon", "nominatim"],
+        ]
+    );
+    let asked: Vec<String> = nominatim.requests().iter().filter_map(|r| r.query("q")).collect();
+    assert_eq!(asked, ["2 Oak Ave", "3 Elm Rd"]);
+}
+
+#[test]
+fn json_post_sends_the_address_in_the_body() {
+    let server = MockServer::start(|request| {
+        let address = request.json()["address"].as_str().unwrap().to_string();
+        Response::json(google_result(&address, "Bethel"))
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--provider",
+            "json-post",
+            "--base-url",
+            &format!("{}/geocode", server.url),
+            "--language",
+            "en",
+        ],
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].target, "/geocode");
+    assert_eq!(requests[0].json(), json!({ "address": "1 Main St", "language": "en" }));
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv"))[1],
+        ["1 Main St, Bethel, OH, USA", "Bethel"]
+    );
+}
+
+#[test]
+fn html_response_gets_a_friendly_error() {
+    let server = MockServer::start(|_| Response::html("<html>\n  <body>Please sign in</body>\n</html>"));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    run_ok(dir.path(), &["--input", "input.txt", "--base-url", &server.url]);
+
+    // Another try would only get the same page back.
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(
+        read_csv(&dir.path().join("failures.csv"))[1],
+        [
+            "1 Main St",
+            "Received a non-JSON response, check the base URL and any proxy in between: \
+             <html> <body>Please sign in</body> </html>"
+        ]
+    );
+}
+
+#[test]
+fn unexpected_json_gets_a_friendly_error() {
+    let server = MockServer::start(|_| Response::json(json!({ "status": "OK", "results": "none" })));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    run_ok(dir.path(), &["--input", "input.txt", "--base-url", &server.url]);
+
+    let failures = read_csv(&dir.path().join("failures.csv"));
+    assert!(
+        failures[1][1].starts_with("Received JSON in an unexpected shape"),
+        "{:?}",
+        failures
+    );
+    assert!(
+        failures[1][1].ends_with("the provider may have changed its format: {\"results\":\"none\",\"status\":\"OK\"}")
+    );
+}
+
+#[test]
+fn nominatim_importance_is_the_confidence() {
+    let google = MockServer::start(|request| match request.query("address").unwrap().as_str() {
+        "1 Main St" => Response::json(google_result("1 Main St", "Bethel")),
+        _ => Response::json(common::google_zero_results()),
+    });
+    let nominatim = MockServer::start(|_| {
+        let mut place = common::nominatim_place("Enon");
+        place[0]["importance"] = json!(0.73);
+        Response::json(place)
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--provider",
+            "google,nominatim",
+            "--base-url",
+            &format!("google={}", google.url),
+            "--base-url",
+            &format!("nominatim={}", nominatim.url),
+            "--confidence",
+        ],
+    );
+
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Address", "Township", "Provider", "Confidence"],
+            vec!["1 Main St, Bethel, OH, USA", "Bethel", "google", ""],
+            vec!["Enon, Clark County, Ohio, United States", "Enon", "nominatim", "0.73"],
+        ]
+    );
+}
+
+/// A json-post endpoint that answers batches and single addresses alike in the town each address names last.
+fn batch_endpoint() -> MockServer {
+    MockServer::start(|request| {
+        let answer = |address: &str| google_result(address, address.rsplit(' ').next().unwrap());
+        let body = request.json();
+        match body["addresses"].as_array() {
+            // Short one response, like an endpoint that drops what it can't parse.
+            Some(batch) if batch.iter().any(|a| a == "4 Broken Enon") => Response::json(json!([])),
+            Some(batch) => Response::json(batch.iter().map(|a| answer(a.as_str().unwrap())).collect()),
+            None => Response::json(answer(body["address"].as_str().unwrap())),
+        }
+    })
+}
+
+#[test]
+fn batches_map_back_to_their_inputs() {
+    let server = batch_endpoint();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("input.txt"),
+        "1 Main Bethel\n2 Oak Enon\n1 main bethel\n3 Elm Medway\n4 Pine Tremont\n5 Ash Donnelsville",
+    )
+    .unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--provider",
+            "json-post",
+            "--base-url",
+            &server.url,
+            "--batch-size",
+            "2",
+        ],
+    );
+
+    let batches: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|r| r.json()["addresses"].clone())
+        .collect();
+    assert_eq!(
+        batches,
+        [
+            json!(["1 Main Bethel", "2 Oak Enon"]),
+            json!(["3 Elm Medway", "4 Pine Tremont"]),
+            json!(["5 Ash Donnelsville"]),
+        ]
+    );
+    let townships: Vec<String> = read_csv(&dir.path().join("output.csv"))[1..]
+        .iter()
+        .map(|row| row[1].clone())
+        .collect();
+    assert_eq!(
+        townships,
+        ["Bethel", "Enon", "Bethel", "Medway", "Tremont", "Donnelsville"]
+    );
+}
+
+#[test]
+fn unsettled_batches_are_retried_one_address_at_a_time() {
+    let server = batch_endpoint();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "3 Elm Medway\n4 Broken Enon").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--provider",
+            "json-post",
+            "--base-url",
+            &server.url,
+            "--batch-size",
+            "2",
+        ],
+    );
+
+    let singles: Vec<Value> = server.requests()[1..]
+        .iter()
+        .map(|r| r.json()["address"].clone())
+        .collect();
+    assert_eq!(singles, [json!("3 Elm Medway"), json!("4 Broken Enon")]);
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv"))[1..],
+        [
+            vec!["3 Elm Medway, Medway, OH, USA", "Medway"],
+            vec!["4 Broken Enon, Enon, OH, USA", "Enon"]
+        ]
+    );
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::{google_result, read_csv, run_ok, MockServer, Response};
+use serde_json::json;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn retry_budget_stops_retries_across_the_run() {
+    let server = MockServer::start(|_| Response::status(503));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave\n3 Elm Rd").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--max-retries",
+            "3",
+            "--max-total-retries",
+            "2",
+        ],
+    );
+
+    // One attempt per address, plus the two retries the budget allows.
+    assert_eq!(server.requests().len(), 5);
+    let failures = read_csv(&dir.path().join("failures.csv"));
+    assert_eq!(failures.len(), 4);
+    for row in &failures[1..] {
+        assert!(row[1].ends_with("(global retry budget exhausted)"), "{:?}", row);
+    }
+}
+
+/// Says the daily quota is used up for the first `refusals` requests, then answers normally.
+fn quota_runs_out(refusals: usize) -> MockServer {
+    let seen = AtomicUsize::new(0);
+    MockServer::start(move |request| {
+        if seen.fetch_add(1, Ordering::SeqCst) < refusals {
+            return Response::json(json!({
+                "status": "OVER_QUERY_LIMIT",
+                "error_message": "You have exceeded your daily request quota for this API.",
+                "results": [],
+            }));
+        }
+        Response::json(google_result(&request.query("address").unwrap(), "Bethel"))
+    })
+}
+
+#[test]
+fn quota_wait_resumes_once_the_quota_is_back() {
+    let server = quota_runs_out(2);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+
+    let output = run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--quota-wait",
+            "0.002",
+        ],
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout
+            .matches("google's daily quota is used up (OVER_QUERY_LIMIT), pausing for 0s")
+            .count(),
+        2,
+        "{}",
+        stdout
+    );
+    assert_eq!(server.requests().len(), 4);
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 3);
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 1);
+}
+
+#[test]
+fn quota_wait_gives_up_past_its_maximum() {
+    let server = quota_runs_out(usize::MAX);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    let run_against = |server: &MockServer| {
+        run_ok(
+            dir.path(),
+            &[
+                "--input",
+                "input.txt",
+                "--base-url",
+                &server.url,
+                "--cache",
+                "cache.jsonl",
+                "--quota-wait",
+                "0.002",
+                "--max-quota-wait",
+                "0.005",
+            ],
+        );
+    };
+
+    run_against(&server);
+
+    // Two waits fit in the maximum, then each address fails on its next refusal without being retried.
+    assert_eq!(server.requests().len(), 4);
+    let failures = read_csv(&dir.path().join("failures.csv"));
+    assert_eq!(failures.len(), 3);
+    for row in &failures[1..] {
+        assert_eq!(row[1], "google's daily quota is used up (OVER_QUERY_LIMIT)");
+    }
+
+    // The refusals weren't cached, so a run after the quota resets gets real answers.
+    let healthy = quota_runs_out(0);
+    run_against(&healthy);
+    assert_eq!(healthy.requests().len(), 2);
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 3);
+}
+
+/// Answers each address with `first` the first time it's asked for, and normally after that.
+fn fails_once(first: Response) -> MockServer {
+    let asked = std::sync::Mutex::new(std::collections::HashSet::new());
+    MockServer::start(move |request| {
+        let address = request.query("address").unwrap();
+        if asked.lock().unwrap().insert(address.clone()) {
+            return first.clone();
+        }
+        Response::json(google_result(&address, "Bethel"))
+    })
+}
+
+#[test]
+fn custom_retryable_status_is_retried() {
+    let proxy_error = Response::json(json!({ "status": "PROXY_ERROR", "results": [] }));
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let server = fails_once(proxy_error.clone());
+    run_ok(dir.path(), &["--input", "input.txt", "--base-url", &server.url]);
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(
+        read_csv(&dir.path().join("failures.csv"))[1],
+        ["1 Main St", "No township found (status PROXY_ERROR)"]
+    );
+
+    let server = fails_once(proxy_error);
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--retry-statuses",
+            "PROXY_ERROR",
+        ],
+    );
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 2);
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 1);
+}
+
+#[test]
+fn custom_retryable_http_codes_replace_the_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+    let run_against = |server: &MockServer| {
+        run_ok(
+            dir.path(),
+            &[
+                "--input",
+                "input.txt",
+                "--base-url",
+                &server.url,
+                "--retry-http-codes",
+                "418",
+            ],
+        );
+    };
+
+    let server = fails_once(Response::status(418));
+    run_against(&server);
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 2);
+
+    let server = fails_once(Response::status(503));
+    run_against(&server);
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 2);
+}
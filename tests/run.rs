@@ -0,0 +1,636 @@
+mod common;
+
+use common::{google_result, google_zero_results, read_csv, run, run_ok, MockServer, Request, Response};
+use serde_json::Value;
+use std::fs;
+
+/// Answers every address in Bethel, marking the ones that mention "partial" as partial matches. Addresses that
+/// mention "two" get a second result in Enon, which is never a partial match, and ones that mention "nowhere" get
+/// no results at all.
+fn google() -> MockServer {
+    MockServer::start(answer)
+}
+
+fn answer(request: &Request) -> Response {
+    let address = request.query("address").unwrap();
+    if address.contains("nowhere") {
+        return Response::json(google_zero_results());
+    }
+    let mut response: Value = google_result(&address, "Bethel");
+    if address.contains("partial") {
+        response["results"][0]["partial_match"] = Value::Bool(true);
+    }
+    if address.contains("two") {
+        let second = google_result(&address, "Enon")["results"][0].clone();
+        response["results"].as_array_mut().unwrap().push(second);
+    }
+    Response::json(response)
+}
+
+#[test]
+fn drop_partial_sends_partial_matches_to_failures() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 partial Rd").unwrap();
+
+    run_ok(
+        dir.path(),
+        &["--input", "input.txt", "--base-url", &server.url, "--drop-partial"],
+    );
+
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Address", "Township"],
+            vec!["1 Main St, Bethel, OH, USA", "Bethel"]
+        ]
+    );
+    let failures = read_csv(&dir.path().join("failures.csv"));
+    assert_eq!(failures.len(), 2);
+    assert_eq!(failures[1][0], "2 partial Rd");
+}
+
+#[test]
+fn partial_matches_are_kept_without_drop_partial() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 partial Rd").unwrap();
+
+    run_ok(dir.path(), &["--input", "input.txt", "--base-url", &server.url]);
+
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 3);
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 1);
+}
+
+#[test]
+fn unrelated_output_csv_is_left_alone() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+    fs::write(dir.path().join("output.csv"), "not ours").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--output",
+            "elsewhere.csv",
+        ],
+    );
+
+    assert_eq!(fs::read_to_string(dir.path().join("output.csv")).unwrap(), "not ours");
+    assert_eq!(read_csv(&dir.path().join("elsewhere.csv")).len(), 2);
+}
+
+#[test]
+fn yes_skips_the_confirmation() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    fs::write(dir.path().join("output.csv"), "previous run").unwrap();
+    let args = [
+        "--input",
+        "input.txt",
+        "--base-url",
+        &server.url,
+        "--confirm-above",
+        "1",
+    ];
+
+    // Nothing to answer the question on, so the run is refused and the previous output kept.
+    let refused = run(dir.path(), &args);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("pass --yes to go ahead"));
+    assert!(server.requests().is_empty());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("output.csv")).unwrap(),
+        "previous run"
+    );
+
+    run_ok(dir.path(), &[&args[..], &["--yes"]].concat());
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 3);
+}
+
+#[test]
+fn flatten_results_writes_a_row_per_result() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 two Rd").unwrap();
+
+    let output = run_ok(
+        dir.path(),
+        &["--input", "input.txt", "--base-url", &server.url, "--flatten-results"],
+    );
+
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Input Address", "Address", "Township"],
+            vec!["1 Main St", "1 Main St, Bethel, OH, USA", "Bethel"],
+            vec!["2 two Rd", "2 two Rd, Bethel, OH, USA", "Bethel"],
+            vec!["2 two Rd", "2 two Rd, Enon, OH, USA", "Enon"],
+        ]
+    );
+    // Two addresses resolved, however many rows they made.
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Resolved 2 addresses, 0 failed."));
+}
+
+#[test]
+fn flatten_results_drops_partial_matches_one_result_at_a_time() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "2 two partial Rd").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--flatten-results",
+            "--drop-partial",
+        ],
+    );
+
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Input Address", "Address", "Township"],
+            vec!["2 two partial Rd", "2 two partial Rd, Enon, OH, USA", "Enon"]
+        ]
+    );
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 1);
+}
+
+#[test]
+fn warns_about_township_types_that_never_matched() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+    let warnings = |types: &[&str]| -> Vec<String> {
+        let output = run_ok(
+            dir.path(),
+            &[&["--input", "input.txt", "--base-url", &server.url], types].concat(),
+        );
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.starts_with("Warning: township type"))
+            .map(str::to_string)
+            .collect()
+    };
+
+    // Bethel comes from its locality. `political` is on that same component, but it comes second so it never wins.
+    assert_eq!(
+        warnings(&["--township-types", "locality,political,localty"]),
+        [
+            "Warning: township type 'political' never supplied a township, check --township-types and \
+             --country-township-types for typos",
+            "Warning: township type 'localty' never supplied a township, check --township-types and \
+             --country-township-types for typos",
+        ]
+    );
+    assert_eq!(
+        warnings(&["--country-township-types", "US=admin_levl_3"]),
+        [
+            "Warning: township type 'admin_levl_3' never supplied a township, check --township-types and \
+             --country-township-types for typos",
+        ]
+    );
+
+    // The default types aren't the user's to fix, so they're never warned about.
+    let output = run_ok(dir.path(), &["--input", "input.txt", "--base-url", &server.url]);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Warning: township type"));
+}
+
+#[test]
+fn plain_failures_list_just_the_failed_inputs() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("input.txt"),
+        "1 Main St\n  nowhere 1\n2 Oak Ave\nnowhere, \"2\"",
+    )
+    .unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--failures-plain",
+            "failed.txt",
+        ],
+    );
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("failed.txt")).unwrap(),
+        "  nowhere 1\nnowhere, \"2\"\n"
+    );
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 3);
+}
+
+#[test]
+fn suffix_is_appended_to_the_query() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St \n2 Oak Ave").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--prepend-prefix",
+            "Lot ",
+            "--append-suffix",
+            ", Springfield, OH",
+            "--input-address",
+        ],
+    );
+
+    let mut queried: Vec<String> = server.requests().iter().filter_map(|r| r.query("address")).collect();
+    queried.sort();
+    assert_eq!(
+        queried,
+        ["Lot 1 Main St, Springfield, OH", "Lot 2 Oak Ave, Springfield, OH"]
+    );
+    // The output still shows each address as it was given.
+    let output = read_csv(&dir.path().join("output.csv"));
+    assert_eq!(output[1][0], "1 Main St ");
+}
+
+#[test]
+fn results_outside_the_expected_state_are_flagged() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let output = run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--expect-state",
+            "IN",
+        ],
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 results were outside the expected state."));
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 2);
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--expect-state",
+            "IN",
+            "--strict-region",
+        ],
+    );
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 1);
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 2);
+}
+
+#[test]
+fn strict_region_fails_an_input_once_for_all_its_results() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "2 two Rd").unwrap();
+
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--expect-state",
+            "IN",
+            "--strict-region",
+            "--flatten-results",
+        ],
+    );
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 2);
+}
+
+#[test]
+fn results_in_the_expected_state_pass() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let output = run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--expect-state",
+            "oh",
+            "--strict-region",
+        ],
+    );
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("outside the expected state"));
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 2);
+}
+
+#[test]
+fn resumed_run_matches_an_uninterrupted_one() {
+    let input = "1 Main St\n2 two Rd\n1 MAIN ST\n3 Elm Rd\n2 two Rd";
+    let run_with = |dir: &std::path::Path, base_url: &str| {
+        run_ok(
+            dir,
+            &[
+                "--input",
+                "input.txt",
+                "--base-url",
+                base_url,
+                "--cache",
+                "cache.json",
+                "--flatten-results",
+                "--max-retries",
+                "0",
+            ],
+        );
+    };
+
+    let server = google();
+    let uninterrupted = tempfile::tempdir().unwrap();
+    fs::write(uninterrupted.path().join("input.txt"), input).unwrap();
+    run_with(uninterrupted.path(), &server.url);
+
+    // The first attempt loses "3 Elm Rd" to a server error, then dies while writing the cache and the output.
+    let flaky = MockServer::start(|request| match request.query("address").unwrap().as_str() {
+        "3 Elm Rd" => Response::status(500),
+        _ => answer(request),
+    });
+    let resumed = tempfile::tempdir().unwrap();
+    fs::write(resumed.path().join("input.txt"), input).unwrap();
+    run_with(resumed.path(), &flaky.url);
+    assert_eq!(read_csv(&resumed.path().join("failures.csv")).len(), 2);
+    let mut cache = fs::OpenOptions::new()
+        .append(true)
+        .open(resumed.path().join("cache.json"))
+        .unwrap();
+    std::io::Write::write_all(&mut cache, b"{\"key\":{\"provider\":\"goo").unwrap();
+    fs::write(resumed.path().join("output.csv"), "Address,Town").unwrap();
+
+    let before = server.requests().len();
+    run_with(resumed.path(), &server.url);
+
+    // Only the address the first attempt never got is requested again.
+    let requests = server.requests();
+    assert_eq!(requests.len() - before, 1);
+    assert_eq!(requests.last().unwrap().query("address").unwrap(), "3 Elm Rd");
+    for file in ["output.csv", "failures.csv"] {
+        assert_eq!(
+            fs::read_to_string(resumed.path().join(file)).unwrap(),
+            fs::read_to_string(uninterrupted.path().join(file)).unwrap(),
+            "{} differs",
+            file
+        );
+    }
+}
+
+#[test]
+fn strip_plus_codes_sends_plus_code_results_to_failures() {
+    let server = MockServer::start(|request| {
+        let address = request.query("address").unwrap();
+        let mut response = google_result(&address, "Bethel");
+        if address == "out in a field" {
+            response["results"][0]["formatted_address"] = "9G8F+6W Bethel, OH, USA".into();
+        }
+        Response::json(response)
+    });
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\nout in a field").unwrap();
+    let args = ["--input", "input.txt", "--base-url", &server.url];
+
+    run_ok(dir.path(), &args);
+    assert_eq!(read_csv(&dir.path().join("output.csv")).len(), 3);
+
+    run_ok(dir.path(), &[&args[..], &["--strip-plus-codes"]].concat());
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv")),
+        [
+            vec!["Address", "Township"],
+            vec!["1 Main St, Bethel, OH, USA", "Bethel"]
+        ]
+    );
+    assert_eq!(
+        read_csv(&dir.path().join("failures.csv")),
+        [vec!["Address", "Reason"], vec!["out in a field", "Plus Code result"]]
+    );
+}
+
+#[test]
+fn enrich_fills_in_only_the_empty_townships() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    let before = "Address,Township,Notes\r\n\
+                  1 Main St,Enon,\"kept, \"\"as is\"\"\"\r\n\
+                  2 Oak Ave,,second\r\n\
+                  \"3 Elm Rd\",  Medway ,third\r\n\
+                  4 nowhere Ln,,fourth\r\n\
+                  5 Pine Ct,,\"multi\nline\"\r\n";
+    fs::write(dir.path().join("sheet.csv"), before).unwrap();
+
+    run_ok(dir.path(), &["--enrich", "sheet.csv", "--base-url", &server.url]);
+
+    let asked: Vec<String> = server.requests().iter().filter_map(|r| r.query("address")).collect();
+    assert_eq!(asked, ["2 Oak Ave", "4 nowhere Ln", "5 Pine Ct"]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("sheet.csv")).unwrap(),
+        "Address,Township,Notes\r\n\
+         1 Main St,Enon,\"kept, \"\"as is\"\"\"\r\n\
+         2 Oak Ave,Bethel,second\r\n\
+         \"3 Elm Rd\",  Medway ,third\r\n\
+         4 nowhere Ln,,fourth\r\n\
+         5 Pine Ct,Bethel,\"multi\nline\"\r\n"
+    );
+    assert_eq!(read_csv(&dir.path().join("failures.csv"))[1][0], "4 nowhere Ln");
+}
+
+#[test]
+fn single_address_prints_a_table() {
+    let server = MockServer::start(|request| {
+        let mut response = google_result(&request.query("address").unwrap(), "Bethel");
+        let result = &mut response["results"][0];
+        result["types"] = serde_json::json!(["street_address"]);
+        result["geometry"]["location_type"] = "ROOFTOP".into();
+        result["address_components"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({ "long_name": "45344", "short_name": "45344", "types": ["postal_code"] }));
+        Response::json(response)
+    });
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run_ok(dir.path(), &["--address", "1 Main St", "--base-url", &server.url]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let table: Vec<&str> = stdout.lines().skip_while(|line| !line.starts_with("Field")).collect();
+    assert_eq!(
+        table,
+        [
+            "Field             | Value",
+            "------------------+---------------------------",
+            "Input             | 1 Main St",
+            "Provider          | google",
+            "Formatted Address | 1 Main St, Bethel, OH, USA",
+            "Township          | Bethel",
+            "Matched Type      | locality",
+            "County            | Clark County",
+            "State             | OH",
+            "Postal Code       | 45344",
+            "Lat/Lng           | 39.900000, -84.000000",
+            "Result Types      | street_address",
+            "Match Quality     | ROOFTOP",
+        ]
+    );
+}
+
+#[test]
+fn validate_only_reports_problems_without_geocoding() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n\n42\n1 main st").unwrap();
+
+    let output = run(
+        dir.path(),
+        &["--input", "input.txt", "--base-url", &server.url, "--validate-only"],
+    );
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Found 3 problems in the input"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("input.txt: 4 addresses, 3 unique, 3 problems"),
+        "{}",
+        stdout
+    );
+    assert!(server.requests().is_empty());
+    assert!(!dir.path().join("output.csv").exists());
+
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    let output = run_ok(dir.path(), &["--input", "input.txt", "--validate-only"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No problems found"));
+}
+
+#[test]
+fn shuffled_runs_repeat_their_order_and_keep_the_output_in_input_order() {
+    let addresses: Vec<String> = (1..=20).map(|i| format!("{} Main St", i)).collect();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), addresses.join("\n")).unwrap();
+    let order = |seed: &str| {
+        let server = google();
+        run_ok(
+            dir.path(),
+            &[
+                "--input",
+                "input.txt",
+                "--base-url",
+                &server.url,
+                "--shuffle-input",
+                "--seed",
+                seed,
+            ],
+        );
+        let asked: Vec<String> = server.requests().iter().filter_map(|r| r.query("address")).collect();
+        let written: Vec<String> = read_csv(&dir.path().join("output.csv"))[1..]
+            .iter()
+            .map(|row| row[0].trim_end_matches(", Bethel, OH, USA").to_string())
+            .collect();
+        assert_eq!(written, addresses);
+        asked
+    };
+
+    let first = order("7");
+    assert_ne!(first, addresses);
+    assert_eq!(order("7"), first);
+    assert_ne!(order("8"), first);
+}
+
+#[test]
+fn explain_json_traces_each_address() {
+    let server = google();
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+
+    let output = run_ok(
+        dir.path(),
+        &["--input", "input.txt", "--base-url", &server.url, "--explain-json"],
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let traces: Vec<Value> = stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(traces.len(), 1);
+    let trace = &traces[0];
+    assert_eq!(trace["input"], "1 Main St");
+    assert_eq!(trace["provider"], "google");
+    assert_eq!(trace["mode"], "strict");
+    assert_eq!(trace["country"], "US");
+    assert_eq!(trace["chosen"], 0);
+    assert_eq!(trace["township"], "Bethel");
+    assert_eq!(trace["components"][0]["matched"], "locality");
+    assert_eq!(trace["components"][1]["precedence"], 3);
+    assert_eq!(trace["components"][2]["matched"], Value::Null);
+}
+
+#[test]
+fn denied_responses_are_not_cached() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\nnowhere").unwrap();
+    let run_against = |server: &MockServer| {
+        run_ok(
+            dir.path(),
+            &[
+                "--input",
+                "input.txt",
+                "--base-url",
+                &server.url,
+                "--cache",
+                "cache.jsonl",
+            ],
+        );
+    };
+
+    let denied = MockServer::start(|request| match request.query("address").unwrap().as_str() {
+        "nowhere" => Response::json(google_zero_results()),
+        _ => Response::json(serde_json::json!({
+            "status": "REQUEST_DENIED",
+            "error_message": "The provided API key is invalid.",
+            "results": [],
+        })),
+    });
+    run_against(&denied);
+    assert_eq!(read_csv(&dir.path().join("failures.csv")).len(), 3);
+
+    // Once the key is fixed, the denied address is asked for again, while the settled one comes from the cache.
+    let server = google();
+    run_against(&server);
+    let asked: Vec<String> = server.requests().iter().filter_map(|r| r.query("address")).collect();
+    assert_eq!(asked, ["1 Main St"]);
+    assert_eq!(
+        read_csv(&dir.path().join("output.csv"))[1..],
+        [vec!["1 Main St, Bethel, OH, USA", "Bethel"]]
+    );
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{google_result, google_zero_results, run_ok, MockServer, Response};
+use serde_json::{json, Value};
+use std::fs;
+
+fn google(township: &'static str) -> MockServer {
+    MockServer::start(move |request| {
+        let address = request.query("address").unwrap();
+        Response::json(google_result(&address, township))
+    })
+}
+
+#[test]
+fn verify_reports_changed_townships() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St\n2 Oak Ave").unwrap();
+    let before = google("Bethel");
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &before.url,
+            "--cache",
+            "cache.jsonl",
+        ],
+    );
+
+    let after = MockServer::start(|request| {
+        let address = request.query("address").unwrap();
+        let township = if address == "2 Oak Ave" { "Enon" } else { "Bethel" };
+        Response::json(google_result(&address, township))
+    });
+    let output = run_ok(
+        dir.path(),
+        &["--verify", "--cache", "cache.jsonl", "--base-url", &after.url],
+    );
+
+    assert_eq!(after.requests().len(), 2);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1 of 2 townships have changed:"), "{}", stdout);
+    let changed: Vec<&str> = stdout.lines().filter(|line| line.starts_with("google")).collect();
+    assert_eq!(changed.len(), 1);
+    let cells: Vec<&str> = changed[0].split('|').map(str::trim).collect();
+    assert_eq!(cells, ["google", "2 Oak Ave", "Bethel", "Enon"]);
+}
+
+#[test]
+fn verify_finds_nothing_when_the_townships_still_match() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("input.txt"), "1 Main St").unwrap();
+    let server = google("Bethel");
+    run_ok(
+        dir.path(),
+        &[
+            "--input",
+            "input.txt",
+            "--base-url",
+            &server.url,
+            "--cache",
+            "cache.jsonl",
+        ],
+    );
+
+    let output = run_ok(
+        dir.path(),
+        &["--verify", "--cache", "cache.jsonl", "--base-url", &server.url],
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("All 1 verified townships still match."));
+}
+
+/// Writes a cache holding a Google response for each address.
+fn write_cache(path: &std::path::Path, responses: &[(&str, Value)]) {
+    let lines: Vec<String> = responses
+        .iter()
+        .map(|(address, response)| {
+            json!({ "provider": "google", "address": address, "response": response }).to_string() + "\n"
+        })
+        .collect();
+    fs::write(path, lines.concat()).unwrap();
+}
+
+#[test]
+fn diff_cache_lists_the_changed_townships() {
+    let dir = tempfile::tempdir().unwrap();
+    write_cache(
+        &dir.path().join("old.jsonl"),
+        &[
+            ("1 Main St", google_result("1 Main St", "Bethel")),
+            ("2 Oak Ave", google_result("2 Oak Ave", "Bethel")),
+            ("3 Elm Rd", google_result("3 Elm Rd", "Bethel")),
+            ("4 Gone Ln", google_result("4 Gone Ln", "Bethel")),
+        ],
+    );
+    write_cache(
+        &dir.path().join("new.jsonl"),
+        &[
+            ("3 Elm Rd", google_zero_results()),
+            ("2 Oak Ave", google_result("2 Oak Ave", "Enon")),
+            ("1 Main St", google_result("1 Main St", "Bethel")),
+            ("5 New Ct", google_result("5 New Ct", "Enon")),
+        ],
+    );
+
+    let output = run_ok(dir.path(), &["--diff-cache", "old.jsonl", "new.jsonl"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Compared 3 addresses in both caches, 1 only in old.jsonl, 1 only in new.jsonl."),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("2 townships changed:"), "{}", stdout);
+    let changed: Vec<Vec<&str>> = stdout
+        .lines()
+        .filter(|line| line.starts_with("google"))
+        .map(|line| line.split('|').map(str::trim).collect())
+        .collect();
+    assert_eq!(changed.len(), 2);
+    assert_eq!(changed[0], ["google", "2 Oak Ave", "Bethel", "Enon"]);
+    assert_eq!(
+        changed[1],
+        [
+            "google",
+            "3 Elm Rd",
+            "Bethel",
+            "No township found (status ZERO_RESULTS)"
+        ]
+    );
+}